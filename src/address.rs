@@ -0,0 +1,100 @@
+// A public data address is a hex encoded 32 byte xorname
+const PUBLIC_ADDRESS_LEN: usize = 64;
+
+// Pointer, scratchpad and graph entry addresses are hex encoded 48 byte BLS public keys
+const KEY_ADDRESS_LEN: usize = 96;
+
+// Key addresses can't be told apart by their hex, nor from a small private datamap, so they
+// always need their prefix, e.g. `pointer:8c3f...`
+pub const POINTER_PREFIX: &str = "pointer:";
+pub const SCRATCHPAD_PREFIX: &str = "scratchpad:";
+pub const GRAPH_ENTRY_PREFIX: &str = "graph:";
+
+const PREFIXES: [(&str, AddressKind); 3] = [
+    (POINTER_PREFIX, AddressKind::Pointer),
+    (SCRATCHPAD_PREFIX, AddressKind::Scratchpad),
+    (GRAPH_ENTRY_PREFIX, AddressKind::GraphEntry),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    Public,
+    // Hex encoded DataMapChunk, shared by the uploader of private data
+    PrivateDataMap,
+    // Mutable pointer to the current version of some data, resolved before downloading
+    Pointer,
+    // Small mutable blob, downloaded as its current payload
+    Scratchpad,
+    // One revision in a chain of versions, browsed rather than downloaded
+    GraphEntry,
+}
+
+impl AddressKind {
+    pub fn detect(address: &str) -> Option<Self> {
+        for (prefix, kind) in PREFIXES {
+            if let Some(key) = address.strip_prefix(prefix) {
+                return (is_hex(key) && key.len() == KEY_ADDRESS_LEN).then_some(kind);
+            }
+        }
+        if !is_hex(address) {
+            return None;
+        }
+
+        match address.len() {
+            PUBLIC_ADDRESS_LEN => Some(AddressKind::Public),
+            len if len > PUBLIC_ADDRESS_LEN && len % 2 == 0 => Some(AddressKind::PrivateDataMap),
+            _ => None,
+        }
+    }
+}
+
+// The hex part of an address, without its prefix
+pub fn hex_part(address: &str) -> &str {
+    PREFIXES
+        .iter()
+        .find_map(|(prefix, _)| address.strip_prefix(prefix))
+        .unwrap_or(address)
+}
+
+fn is_hex(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_key_addresses() {
+        let pointer = "a".repeat(KEY_ADDRESS_LEN);
+        assert_eq!(
+            AddressKind::detect(&format!("{POINTER_PREFIX}{pointer}")),
+            Some(AddressKind::Pointer)
+        );
+        assert_eq!(hex_part(&format!("{POINTER_PREFIX}{pointer}")), pointer);
+
+        let scratchpad = format!("{SCRATCHPAD_PREFIX}{pointer}");
+        assert_eq!(
+            AddressKind::detect(&scratchpad),
+            Some(AddressKind::Scratchpad)
+        );
+        assert_eq!(hex_part(&scratchpad), pointer);
+        assert_eq!(
+            AddressKind::detect(&format!("{GRAPH_ENTRY_PREFIX}{pointer}")),
+            Some(AddressKind::GraphEntry)
+        );
+
+        // Prefixes only go with key addresses
+        let public = "b".repeat(PUBLIC_ADDRESS_LEN);
+        assert_eq!(AddressKind::detect(&public), Some(AddressKind::Public));
+        assert_eq!(
+            AddressKind::detect(&format!("{POINTER_PREFIX}{public}")),
+            None
+        );
+        // Without a prefix, key length hex is taken for a datamap
+        assert_eq!(
+            AddressKind::detect(&pointer),
+            Some(AddressKind::PrivateDataMap)
+        );
+    }
+}
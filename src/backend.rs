@@ -0,0 +1,174 @@
+use crate::address::{self, AddressKind};
+use crate::server::{GraphVersion, OpenedData, Server, ServerError, MAX_GRAPH_VERSIONS};
+use crate::{ResolvedPointer, ScratchpadInfo};
+
+use autonomi::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+
+pub type DataStream = BoxStream<'static, Result<Bytes, ServerError>>;
+
+#[derive(Debug, Clone)]
+pub struct DataInfo {
+    pub size: usize,
+    // Set when the address is a scratchpad
+    pub scratchpad: Option<ScratchpadInfo>,
+    // Data small enough to come whole with its details, like a scratchpad payload. It is saved
+    // from here rather than fetched again, which could give a newer version.
+    pub content: Option<Bytes>,
+}
+
+// What the download task needs from the network, so it can run against a mock in tests
+pub trait DownloadBackend: Send + Sync + Sized + 'static {
+    // Data looked up by `open`, streamed from without looking it up again
+    type Data: Send + Sync;
+
+    fn connect(environment: &str) -> impl Future<Output = Result<Self, ServerError>> + Send;
+
+    // Follow a pointer address to its data, None for any other address
+    fn resolve(
+        &self,
+        address: &str,
+    ) -> impl Future<Output = Result<Option<ResolvedPointer>, ServerError>> + Send;
+
+    // The details of the data at an address, with what it takes to stream it
+    fn open(
+        &self,
+        address: &str,
+    ) -> impl Future<Output = Result<(DataInfo, Self::Data), ServerError>> + Send;
+
+    // Up to `parallel_chunks` ranges may be fetched at once, they are yielded in order
+    fn stream(
+        &self,
+        data: &Self::Data,
+        offset: usize,
+        parallel_chunks: usize,
+    ) -> impl Future<Output = Result<DataStream, ServerError>> + Send;
+
+    fn graph_entry(
+        &self,
+        address: &str,
+    ) -> impl Future<Output = Result<GraphVersion, ServerError>> + Send;
+
+    // Walk a graph from one entry through all the entries following it, in the order they are
+    // reached, so a chain of revisions comes out oldest first
+    fn graph_history(
+        &self,
+        address: &str,
+    ) -> impl Future<Output = Result<Vec<GraphVersion>, ServerError>> + Send {
+        async move {
+            let start = address::hex_part(address).to_lowercase();
+            eprintln!("Walking graph from entry: {address}");
+
+            let mut versions = Vec::new();
+            let mut seen = HashSet::from([start.clone()]);
+            let mut next = VecDeque::from([start]);
+            while let Some(entry_address) = next.pop_front() {
+                if versions.len() == MAX_GRAPH_VERSIONS {
+                    eprintln!("Stopping after {MAX_GRAPH_VERSIONS} versions of {address}");
+                    break;
+                }
+
+                let version = self.graph_entry(&entry_address).await?;
+                // Graphs may join again, every entry is listed once
+                for descendant in &version.descendants {
+                    if seen.insert(descendant.clone()) {
+                        next.push_back(descendant.clone());
+                    }
+                }
+                versions.push(version);
+            }
+
+            Ok(versions)
+        }
+    }
+}
+
+impl DownloadBackend for Server {
+    type Data = OpenedData;
+
+    async fn connect(environment: &str) -> Result<Self, ServerError> {
+        Server::new(environment).await
+    }
+
+    async fn resolve(&self, address: &str) -> Result<Option<ResolvedPointer>, ServerError> {
+        self.resolve_pointer(address).await
+    }
+
+    async fn open(&self, address: &str) -> Result<(DataInfo, OpenedData), ServerError> {
+        if AddressKind::detect(address) == Some(AddressKind::Scratchpad) {
+            let (scratchpad, data) = self.scratchpad(address).await?;
+            let info = DataInfo {
+                size: data.len(),
+                scratchpad: Some(scratchpad),
+                content: Some(data.clone()),
+            };
+            return Ok((info, OpenedData::from_bytes(data)));
+        }
+
+        let data = self.open_data(address).await?;
+        let info = DataInfo {
+            size: data.size,
+            scratchpad: None,
+            content: None,
+        };
+        Ok((info, data))
+    }
+
+    async fn stream(
+        &self,
+        data: &OpenedData,
+        offset: usize,
+        parallel_chunks: usize,
+    ) -> Result<DataStream, ServerError> {
+        Ok(data.stream_from(offset, parallel_chunks).boxed())
+    }
+
+    async fn graph_entry(&self, address: &str) -> Result<GraphVersion, ServerError> {
+        Server::graph_entry(self, address).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBackend;
+
+    fn entry(n: usize) -> String {
+        format!("{n:096x}")
+    }
+
+    #[tokio::test]
+    async fn walks_graph_once_per_entry() {
+        // 1 -> 2 and 3, which both lead to 4
+        let mock = MockBackend::new()
+            .with_graph_entry(&entry(1), "c1", &[&entry(2), &entry(3)])
+            .with_graph_entry(&entry(2), "c2", &[&entry(4)])
+            .with_graph_entry(&entry(3), "c3", &[&entry(4)])
+            .with_graph_entry(&entry(4), "c4", &[]);
+
+        let versions = mock
+            .graph_history(&format!("{}{}", address::GRAPH_ENTRY_PREFIX, entry(1)))
+            .await
+            .unwrap();
+        let contents: Vec<_> = versions.iter().map(|v| v.content.as_str()).collect();
+        assert_eq!(contents, ["c1", "c2", "c3", "c4"]);
+    }
+
+    #[tokio::test]
+    async fn stops_walking_long_graphs() {
+        let mut mock = MockBackend::new();
+        for n in 0..MAX_GRAPH_VERSIONS + 5 {
+            mock = mock.with_graph_entry(&entry(n), "content", &[&entry(n + 1)]);
+        }
+
+        let versions = mock.graph_history(&entry(0)).await.unwrap();
+        assert_eq!(versions.len(), MAX_GRAPH_VERSIONS);
+        assert_eq!(
+            versions.last().unwrap().address,
+            entry(MAX_GRAPH_VERSIONS - 1)
+        );
+    }
+}
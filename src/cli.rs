@@ -0,0 +1,538 @@
+use ant_download::address::AddressKind;
+use ant_download::backend::DownloadBackend;
+use ant_download::config;
+use ant_download::server::{ChunkReport, Server, ServerError, DEFAULT_ENVIRONMENT};
+use ant_download::{
+    default_filename, format_file_size, parse_addresses, version_filename, ConflictPolicy,
+    DownloadError, DownloadEvent, DownloadManager, DownloadState,
+};
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const EXIT_OK: i32 = 0;
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONNECTION: i32 = 3;
+const EXIT_INVALID_ADDRESS: i32 = 4;
+const EXIT_NETWORK: i32 = 5;
+const EXIT_DATA: i32 = 6;
+const EXIT_FILE: i32 = 7;
+const EXIT_CHUNK_MISSING: i32 = 8;
+
+const USAGE: &str = "\
+Usage: ant_download get [ADDRESS [.ext]]... [OPTIONS]
+       ant_download chunk [CHUNK_ADDRESS]... [OPTIONS]
+
+Download files from the Autonomi Network without opening a window.
+Addresses can be followed by an extension, like in the app. A graph entry
+(graph:<address>) downloads every version that follows it.
+
+Options:
+  -o, --output <PATH>   File to save to, or directory when downloading several addresses
+                        or when the path ends with /
+  -i, --input <FILE>    Read addresses from a file, use - for stdin
+  -e, --env <ENV>       Network to use: local, autonomi, alpha or one from the config
+                        file (default: autonomi)
+  -c, --on-conflict <POLICY>
+                        When a file exists: rename, overwrite or skip (default: rename)
+  -h, --help            Show this help
+
+Exit codes:
+  0  all downloads succeeded
+  2  invalid command line
+  3  could not connect to the network
+  4  invalid address
+  5  network error while downloading
+  6  data could not be decoded
+  7  could not read input or write output files
+
+When several downloads fail, the exit code of the first failure is returned.
+Run `ant_download chunk --help` to fetch single chunks when looking into a failed download.";
+
+const CHUNK_USAGE: &str = "\
+Usage: ant_download chunk [CHUNK_ADDRESS]... [OPTIONS]
+
+Fetch chunks by their address, to find out which part of a failed download is missing.
+Each chunk found is saved as stored on the network, still encrypted, to <CHUNK_ADDRESS>.chunk.
+Prints one tab separated line per chunk to stdout: address, found or missing, size, time
+taken and where it was saved. Everything else goes to stderr.
+
+Options:
+  -d, --data <ADDRESS>  Also fetch the chunks of this data address or private datamap
+  -o, --output <DIR>    Directory to save chunks to (default: current directory)
+  -e, --env <ENV>       Network to use: local, autonomi, alpha or one from the config
+                        file (default: autonomi)
+  -h, --help            Show this help
+
+Exit codes are the same as for get, plus:
+  8  a chunk was not found";
+
+struct ChunkArgs {
+    addresses: Vec<String>,
+    data: Vec<String>,
+    output: Option<PathBuf>,
+    environment: String,
+}
+
+struct GetArgs {
+    addresses: Vec<String>,
+    output: Option<PathBuf>,
+    input: Option<String>,
+    environment: String,
+    conflict_policy: ConflictPolicy,
+}
+
+pub fn is_command(args: &[String]) -> bool {
+    matches!(
+        args.get(1).map(String::as_str),
+        Some("get" | "chunk" | "help" | "--help" | "-h")
+    )
+}
+
+pub async fn run(args: &[String]) -> i32 {
+    match args.get(1).map(String::as_str) {
+        Some("get") => run_get(&args[2..]).await,
+        Some("chunk") => run_chunk(&args[2..]).await,
+        _ => {
+            println!("{USAGE}");
+            EXIT_OK
+        }
+    }
+}
+
+async fn run_get(args: &[String]) -> i32 {
+    let get_args = match parse_get_args(args) {
+        Ok(Some(get_args)) => get_args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return EXIT_OK;
+        }
+        Err(error) => {
+            eprintln!("{error}\n\n{USAGE}");
+            return EXIT_USAGE;
+        }
+    };
+
+    // Gather addresses from the arguments and the input file or stdin
+    let mut text = get_args.addresses.join(" ");
+    if let Some(input) = &get_args.input {
+        match read_input(input) {
+            Ok(content) => {
+                text.push('\n');
+                text.push_str(&content);
+            }
+            Err(error) => {
+                eprintln!("Failed to read addresses from {input}: {error}");
+                return EXIT_FILE;
+            }
+        }
+    }
+
+    // The same address listed twice is downloaded once
+    let mut seen = HashSet::new();
+    let addresses: Vec<_> = parse_addresses(&text)
+        .into_iter()
+        .filter(|(address, _)| {
+            let first = seen.insert(address.clone());
+            if !first {
+                eprintln!("Ignoring duplicate address {address}");
+            }
+            first
+        })
+        .collect();
+    if addresses.is_empty() {
+        eprintln!("No address to download\n\n{USAGE}");
+        return EXIT_USAGE;
+    }
+
+    // Same engine as the app: queued, retried and resumed downloads
+    let mut manager: DownloadManager = DownloadManager::default();
+    manager.verify_completed = false;
+    manager.conflict_policy = get_args.conflict_policy;
+
+    // Graph entries stand for their versions, which are listed and all downloaded
+    let mut expanded = Vec::new();
+    for (address, extension) in addresses {
+        if AddressKind::detect(&address) != Some(AddressKind::GraphEntry) {
+            expanded.push((address, extension));
+            continue;
+        }
+        let versions = match manager.connections().get(&get_args.environment).await {
+            Ok(server) => server.graph_history(&address).await,
+            Err(error) => Err(error),
+        };
+        let versions = match versions {
+            Ok(versions) => versions,
+            Err(error) => {
+                eprintln!("Failed to list the versions of {address}: {error}");
+                return server_exit_code(&error);
+            }
+        };
+        eprintln!("{address} has {} versions:", versions.len());
+        for (index, version) in versions.into_iter().enumerate() {
+            eprintln!("  {}: {}", index + 1, version.content);
+            let filename = version_filename(index + 1, &version.content);
+            expanded.push((version.content, Some(filename)));
+        }
+    }
+    let addresses = expanded;
+
+    // Like the app: a file path for one address, a directory for several
+    let targets: Vec<(String, PathBuf)> = match get_args.output {
+        Some(path) if addresses.len() == 1 && !is_directory(&path) => addresses
+            .into_iter()
+            .map(|(address, _)| (address, path.clone()))
+            .collect(),
+        output => {
+            let dir = output.unwrap_or_else(|| PathBuf::from("."));
+            if let Err(e) = std::fs::create_dir_all(&dir) {
+                eprintln!("Failed to create output directory {dir:?}: {e}");
+                return EXIT_FILE;
+            }
+            addresses
+                .into_iter()
+                .map(|(address, extension)| {
+                    let filename = default_filename(&address, extension.as_deref());
+                    (address, dir.join(filename))
+                })
+                .collect()
+        }
+    };
+
+    let mut addresses = HashMap::new();
+    for (address, path) in targets {
+        let id = manager.enqueue(address.clone(), get_args.environment.clone(), path);
+        if let Some(download) = manager.get(&id) {
+            let path = download.save_path.as_deref().unwrap_or(Path::new(""));
+            match download.status.state {
+                DownloadState::Skipped => eprintln!("Skipping {address}: {path:?} already exists"),
+                _ => eprintln!("Downloading {address} to {path:?}"),
+            }
+        }
+        addresses.insert(id, address);
+    }
+
+    let mut exit = EXIT_OK;
+    while manager.has_pending() {
+        let Some(event) = manager.next_event().await else {
+            break;
+        };
+        let address = &addresses[event.id()];
+        match event {
+            DownloadEvent::ChunkReceived { .. } => {
+                let (received, total) =
+                    manager
+                        .downloads()
+                        .values()
+                        .fold((0, 0), |(received, total), download| {
+                            (
+                                received + download.status.total_bytes_received,
+                                total + download.total_size,
+                            )
+                        });
+                eprint!(
+                    "\r{} of {}   ",
+                    format_file_size(received),
+                    format_file_size(total)
+                );
+                let _ = std::io::stderr().flush();
+            }
+            DownloadEvent::PointerResolved { pointer, .. } => {
+                eprintln!(
+                    "\nPointer {address} points to {} (version {})",
+                    pointer.target, pointer.counter
+                );
+            }
+            DownloadEvent::ScratchpadFetched { scratchpad, .. } => {
+                eprintln!(
+                    "\nScratchpad {address} is at version {} (data encoding {})",
+                    scratchpad.counter, scratchpad.data_encoding
+                );
+            }
+            DownloadEvent::Retrying { attempt, error, .. } => {
+                eprintln!("\nRetrying {address} (attempt {attempt}): {error}");
+            }
+            DownloadEvent::Completed { id, .. } => {
+                // The file may have been renamed after its detected type
+                if let Some(download) = manager.get(&id) {
+                    eprintln!(
+                        "\nSaved {address} to {:?} ({})",
+                        download.save_path.as_deref().unwrap_or(Path::new("")),
+                        format_file_size(download.status.total_bytes_received)
+                    );
+                }
+            }
+            DownloadEvent::Error { error, .. } => {
+                eprintln!("\nFailed to download {address}: {error}");
+                if exit == EXIT_OK {
+                    exit = exit_code(&error);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    exit
+}
+
+async fn run_chunk(args: &[String]) -> i32 {
+    let chunk_args = match parse_chunk_args(args) {
+        Ok(Some(chunk_args)) => chunk_args,
+        Ok(None) => {
+            println!("{CHUNK_USAGE}");
+            return EXIT_OK;
+        }
+        Err(error) => {
+            eprintln!("{error}\n\n{CHUNK_USAGE}");
+            return EXIT_USAGE;
+        }
+    };
+    if chunk_args.addresses.is_empty() && chunk_args.data.is_empty() {
+        eprintln!("No chunk address to fetch\n\n{CHUNK_USAGE}");
+        return EXIT_USAGE;
+    }
+
+    let dir = chunk_args.output.unwrap_or_else(|| PathBuf::from("."));
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        eprintln!("Failed to create output directory {dir:?}: {e}");
+        return EXIT_FILE;
+    }
+
+    let server = match Server::new(&chunk_args.environment).await {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("{error}");
+            return server_exit_code(&error);
+        }
+    };
+
+    let mut addresses = chunk_args.addresses;
+    for data in &chunk_args.data {
+        match server.data_map_chunks(data).await {
+            Ok(chunks) => {
+                eprintln!("{data} is made of {} chunks", chunks.len());
+                addresses.extend(chunks);
+            }
+            Err(error) => {
+                eprintln!("Failed to list the chunks of {data}: {error}");
+                return server_exit_code(&error);
+            }
+        }
+    }
+
+    // Keep going after a missing chunk, the point is to find all of them
+    let mut exit = EXIT_OK;
+    for address in &addresses {
+        let code = match server.fetch_chunk(address).await {
+            Ok(ChunkReport {
+                content: Some(content),
+                elapsed,
+            }) => {
+                let path = dir.join(format!("{address}.chunk"));
+                match std::fs::write(&path, &content) {
+                    Ok(()) => {
+                        println!(
+                            "{address}\tfound\t{}\t{} ms\t{}",
+                            format_file_size(content.len()),
+                            elapsed.as_millis(),
+                            path.display()
+                        );
+                        EXIT_OK
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to save chunk to {path:?}: {e}");
+                        EXIT_FILE
+                    }
+                }
+            }
+            Ok(ChunkReport {
+                content: None,
+                elapsed,
+            }) => {
+                println!("{address}\tmissing\t-\t{} ms", elapsed.as_millis());
+                EXIT_CHUNK_MISSING
+            }
+            Err(error) => {
+                eprintln!("Failed to fetch chunk {address}: {error}");
+                server_exit_code(&error)
+            }
+        };
+        if exit == EXIT_OK {
+            exit = code;
+        }
+    }
+
+    exit
+}
+
+// Returns None when help was requested
+fn parse_chunk_args(args: &[String]) -> Result<Option<ChunkArgs>, String> {
+    let mut chunk_args = ChunkArgs {
+        addresses: Vec::new(),
+        data: Vec::new(),
+        output: None,
+        environment: DEFAULT_ENVIRONMENT.to_string(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-d" | "--data" => chunk_args.data.push(next_value(&mut args, arg)?),
+            "-o" | "--output" => {
+                chunk_args.output = Some(PathBuf::from(next_value(&mut args, arg)?))
+            }
+            "-e" | "--env" => chunk_args.environment = parse_environment(&mut args, arg)?,
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("Unknown option: {flag}"));
+            }
+            address => chunk_args.addresses.push(address.to_string()),
+        }
+    }
+
+    Ok(Some(chunk_args))
+}
+
+// Returns None when help was requested
+fn parse_get_args(args: &[String]) -> Result<Option<GetArgs>, String> {
+    let mut get_args = GetArgs {
+        addresses: Vec::new(),
+        output: None,
+        input: None,
+        environment: DEFAULT_ENVIRONMENT.to_string(),
+        conflict_policy: ConflictPolicy::default(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => get_args.output = Some(PathBuf::from(next_value(&mut args, arg)?)),
+            "-i" | "--input" => get_args.input = Some(next_value(&mut args, arg)?),
+            "-e" | "--env" => get_args.environment = parse_environment(&mut args, arg)?,
+            "-c" | "--on-conflict" => {
+                get_args.conflict_policy = match next_value(&mut args, arg)?.as_str() {
+                    "rename" => ConflictPolicy::Rename,
+                    "overwrite" => ConflictPolicy::Overwrite,
+                    "skip" => ConflictPolicy::Skip,
+                    policy => return Err(format!("Unknown conflict policy: {policy}")),
+                };
+            }
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("Unknown option: {flag}"));
+            }
+            address => get_args.addresses.push(address.to_string()),
+        }
+    }
+
+    Ok(Some(get_args))
+}
+
+fn next_value(args: &mut std::slice::Iter<String>, name: &str) -> Result<String, String> {
+    args.next()
+        .cloned()
+        .ok_or_else(|| format!("Missing value for {name}"))
+}
+
+fn parse_environment(args: &mut std::slice::Iter<String>, name: &str) -> Result<String, String> {
+    let environment = next_value(args, name)?;
+    if !config::load().environment_names().contains(&environment) {
+        return Err(format!("Unknown environment: {environment}"));
+    }
+    Ok(environment)
+}
+
+// A trailing separator asks for a directory, even one that doesn't exist yet
+fn is_directory(path: &Path) -> bool {
+    path.is_dir()
+        || path
+            .as_os_str()
+            .to_string_lossy()
+            .ends_with(std::path::is_separator)
+}
+
+fn read_input(input: &str) -> std::io::Result<String> {
+    if input == "-" {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        Ok(content)
+    } else {
+        std::fs::read_to_string(input)
+    }
+}
+
+fn exit_code(error: &DownloadError) -> i32 {
+    match error {
+        DownloadError::Server(error) => server_exit_code(error),
+        DownloadError::File(_) | DownloadError::Abandoned => EXIT_FILE,
+    }
+}
+
+fn server_exit_code(error: &ServerError) -> i32 {
+    match error {
+        ServerError::Connection(_) | ServerError::Config(_) => EXIT_CONNECTION,
+        ServerError::InvalidAddress(_) => EXIT_INVALID_ADDRESS,
+        ServerError::Network(_) => EXIT_NETWORK,
+        ServerError::Data(_) => EXIT_DATA,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<GetArgs>, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_get_args(&args)
+    }
+
+    #[test]
+    fn parses_get_options() {
+        let get_args = parse(&["abc", "-o", "out/", "--on-conflict", "skip", "def"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(get_args.addresses, ["abc", "def"]);
+        assert_eq!(get_args.output, Some(PathBuf::from("out/")));
+        assert_eq!(get_args.conflict_policy, ConflictPolicy::Skip);
+        assert_eq!(get_args.environment, DEFAULT_ENVIRONMENT);
+
+        let get_args = parse(&["-c", "overwrite", "-i", "-"]).unwrap().unwrap();
+        assert_eq!(get_args.conflict_policy, ConflictPolicy::Overwrite);
+        assert_eq!(get_args.input.as_deref(), Some("-"));
+        assert!(parse(&["abc", "--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_bad_get_options() {
+        assert_eq!(
+            parse(&["abc", "--fast"]).err(),
+            Some("Unknown option: --fast".to_string())
+        );
+        assert_eq!(
+            parse(&["abc", "-o"]).err(),
+            Some("Missing value for -o".to_string())
+        );
+        assert_eq!(
+            parse(&["--on-conflict"]).err(),
+            Some("Missing value for --on-conflict".to_string())
+        );
+        assert_eq!(
+            parse(&["abc", "-c", "replace"]).err(),
+            Some("Unknown conflict policy: replace".to_string())
+        );
+    }
+
+    #[test]
+    fn trailing_separator_means_directory() {
+        let missing =
+            std::env::temp_dir().join(format!("ant_download_cli_{}_missing", std::process::id()));
+        assert!(!is_directory(&missing));
+        assert!(is_directory(Path::new(&format!(
+            "{}{}",
+            missing.display(),
+            std::path::MAIN_SEPARATOR
+        ))));
+        assert!(is_directory(&std::env::temp_dir()));
+    }
+}
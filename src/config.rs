@@ -0,0 +1,199 @@
+use crate::server::ENVIRONMENTS;
+
+use autonomi::{ClientConfig, InitialPeersConfig, Multiaddr};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    // User defined networks, next to the built-in ones
+    #[serde(default)]
+    pub environments: Vec<EnvironmentConfig>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentConfig {
+    pub name: String,
+    // Bootstrap peer multiaddrs
+    #[serde(default)]
+    pub peers: Vec<String>,
+    #[serde(default)]
+    pub network_id: Option<u8>,
+    // Discover peers on the local network
+    #[serde(default)]
+    pub local: bool,
+    #[serde(default)]
+    pub ignore_cache: bool,
+    #[serde(default)]
+    pub bootstrap_cache_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub evm_network: EvmNetworkConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum EvmNetworkConfig {
+    #[default]
+    ArbitrumOne,
+    ArbitrumSepolia,
+    Custom {
+        rpc_url: String,
+        payment_token_address: String,
+        data_payments_address: String,
+    },
+}
+
+impl Config {
+    // Built-in environments first, then the user defined ones
+    pub fn environment_names(&self) -> Vec<String> {
+        ENVIRONMENTS
+            .iter()
+            .map(|name| name.to_string())
+            .chain(self.environments.iter().map(|env| env.name.clone()))
+            .collect()
+    }
+
+    pub fn environment(&self, name: &str) -> Option<&EnvironmentConfig> {
+        self.environments.iter().find(|env| env.name == name)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, env) in self.environments.iter().enumerate() {
+            if env.name.trim().is_empty() {
+                return Err("Every network needs a name".to_string());
+            }
+            if ENVIRONMENTS.contains(&env.name.as_str()) {
+                return Err(format!("{} is a built-in network name", env.name));
+            }
+            if self.environments[..index]
+                .iter()
+                .any(|other| other.name == env.name)
+            {
+                return Err(format!("There are two networks called {}", env.name));
+            }
+            env.peer_addrs()?;
+        }
+        Ok(())
+    }
+}
+
+impl EnvironmentConfig {
+    pub fn peer_addrs(&self) -> Result<Vec<Multiaddr>, String> {
+        self.peers
+            .iter()
+            .map(|peer| {
+                peer.parse()
+                    .map_err(|e| format!("Invalid peer address {peer} for {}: {e}", self.name))
+            })
+            .collect()
+    }
+
+    pub fn client_config(&self) -> Result<ClientConfig, String> {
+        let init_peers_config = InitialPeersConfig {
+            addrs: self.peer_addrs()?,
+            local: self.local,
+            ignore_cache: self.ignore_cache,
+            bootstrap_cache_dir: self.bootstrap_cache_dir.clone(),
+            ..Default::default()
+        };
+
+        let evm_network = match &self.evm_network {
+            EvmNetworkConfig::ArbitrumOne => autonomi::Network::ArbitrumOne,
+            EvmNetworkConfig::ArbitrumSepolia => autonomi::Network::ArbitrumSepoliaTest,
+            EvmNetworkConfig::Custom {
+                rpc_url,
+                payment_token_address,
+                data_payments_address,
+            } => {
+                autonomi::Network::new_custom(rpc_url, payment_token_address, data_payments_address)
+            }
+        };
+
+        Ok(ClientConfig {
+            init_peers_config,
+            evm_network,
+            network_id: self.network_id,
+            ..Default::default()
+        })
+    }
+}
+
+fn config_file() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("ant_download").join(CONFIG_FILE))
+}
+
+pub fn load() -> Config {
+    let Some(path) = config_file() else {
+        return Config::default();
+    };
+
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(_) => return Config::default(), // No config yet
+    };
+
+    serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+        eprintln!("Ignoring unreadable config at {path:?}: {e}");
+        Config::default()
+    })
+}
+
+pub fn save(config: &Config) -> Result<(), String> {
+    config.validate()?;
+    let path = config_file().ok_or("Could not find a config directory")?;
+    crate::save_json(&path, config, "config")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testnet() -> EnvironmentConfig {
+        EnvironmentConfig {
+            name: "testnet".to_string(),
+            peers: vec!["/ip4/10.0.0.1/udp/12000/quic-v1".to_string()],
+            network_id: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lists_custom_environments_after_built_in_ones() {
+        let config = Config {
+            environments: vec![testnet()],
+        };
+        assert_eq!(
+            config.environment_names(),
+            vec!["local", "autonomi", "alpha", "testnet"]
+        );
+        assert_eq!(config.environment("testnet"), Some(&testnet()));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_environments() {
+        let mut config = Config {
+            environments: vec![testnet(), testnet()],
+        };
+        assert!(config.validate().is_err());
+
+        config.environments[1].name = "autonomi".to_string();
+        assert!(config.validate().is_err());
+
+        config.environments[1].name = "other".to_string();
+        config.environments[1].peers = vec!["not a multiaddr".to_string()];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn reads_minimal_config() {
+        let config: Config =
+            serde_json::from_str(r#"{"environments": [{"name": "testnet"}]}"#).unwrap();
+        assert_eq!(
+            config.environments[0].evm_network,
+            EvmNetworkConfig::ArbitrumOne
+        );
+        assert!(config.environments[0].peers.is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::backend::DownloadBackend;
+use crate::server::{Server, ServerError};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ConnectionStatus {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    Failed(String),
+}
+
+type ConnectFuture<B> = Pin<Box<dyn Future<Output = Result<B, ServerError>> + Send>>;
+type Connector<B> = Arc<dyn Fn(String) -> ConnectFuture<B> + Send + Sync>;
+type SharedConnect<B> = Shared<BoxFuture<'static, Result<Arc<B>, ServerError>>>;
+
+struct Connection<B> {
+    // The bootstrap in progress or done, awaited by every download of the environment
+    backend: Mutex<Option<SharedConnect<B>>>,
+    status: Mutex<ConnectionStatus>,
+}
+
+impl<B> Default for Connection<B> {
+    fn default() -> Self {
+        Self {
+            backend: Mutex::new(None),
+            status: Mutex::new(ConnectionStatus::Disconnected),
+        }
+    }
+}
+
+impl<B> Connection<B> {
+    fn set_status(&self, status: ConnectionStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+/// Holds one lazily initialized backend (a `Server` by default) per environment,
+/// shared by all downloads
+pub struct ConnectionManager<B = Server> {
+    connections: Arc<Mutex<HashMap<String, Arc<Connection<B>>>>>,
+    connector: Connector<B>,
+}
+
+impl<B> Clone for ConnectionManager<B> {
+    fn clone(&self) -> Self {
+        Self {
+            connections: self.connections.clone(),
+            connector: self.connector.clone(),
+        }
+    }
+}
+
+impl<B: DownloadBackend> Default for ConnectionManager<B> {
+    fn default() -> Self {
+        Self::with_connector(|environment| async move { B::connect(&environment).await })
+    }
+}
+
+impl<B: DownloadBackend> ConnectionManager<B> {
+    // Use a custom way of connecting, e.g. to hand out a mock backend in tests
+    pub fn with_connector<F, Fut>(connector: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<B, ServerError>> + Send + 'static,
+    {
+        Self {
+            connections: Arc::default(),
+            connector: Arc::new(move |environment| Box::pin(connector(environment))),
+        }
+    }
+
+    pub async fn get(&self, environment: &str) -> Result<Arc<B>, ServerError> {
+        let connection = self.connection(environment);
+
+        // Only one bootstrap runs at a time, the other downloads wait and reuse its client
+        let connect = {
+            let mut backend = connection.backend.lock().unwrap();
+            match backend.as_ref() {
+                // A failed bootstrap is tried again by the next download
+                Some(connect) if !matches!(connect.peek(), Some(Err(_))) => connect.clone(),
+                _ => {
+                    let connect = self.spawn_connect(environment, &connection);
+                    *backend = Some(connect.clone());
+                    connect
+                }
+            }
+        };
+        connect.await
+    }
+
+    // The bootstrap runs in its own task, so a download cancelled or paused while waiting for
+    // it doesn't drop it for the others
+    fn spawn_connect(
+        &self,
+        environment: &str,
+        connection: &Arc<Connection<B>>,
+    ) -> SharedConnect<B> {
+        connection.set_status(ConnectionStatus::Connecting);
+        let connect = (self.connector)(environment.to_string());
+        let connection = connection.clone();
+        let task = tokio::spawn(async move {
+            match connect.await {
+                Ok(backend) => {
+                    connection.set_status(ConnectionStatus::Connected);
+                    Ok(Arc::new(backend))
+                }
+                Err(error) => {
+                    connection.set_status(ConnectionStatus::Failed(error.to_string()));
+                    Err(error)
+                }
+            }
+        });
+
+        async move {
+            task.await.unwrap_or_else(|e| {
+                Err(ServerError::Connection(format!(
+                    "Connecting task failed: {e}"
+                )))
+            })
+        }
+        .boxed()
+        .shared()
+    }
+
+    pub async fn reconnect(&self, environment: &str) -> Result<Arc<B>, ServerError> {
+        let connection = self.connection(environment);
+        connection.backend.lock().unwrap().take();
+        connection.set_status(ConnectionStatus::Disconnected);
+        self.get(environment).await
+    }
+
+    pub fn status(&self, environment: &str) -> ConnectionStatus {
+        self.connections
+            .lock()
+            .unwrap()
+            .get(environment)
+            .map(|connection| connection.status.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    fn connection(&self, environment: &str) -> Arc<Connection<B>> {
+        self.connections
+            .lock()
+            .unwrap()
+            .entry(environment.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBackend;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn bootstrap_outlives_a_dropped_waiter() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let connections = ConnectionManager::with_connector({
+            let attempts = attempts.clone();
+            move |_environment| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(MockBackend::new())
+                }
+            }
+        });
+
+        // Like a download cancelled or paused while connecting
+        let waited = tokio::time::timeout(Duration::from_millis(10), connections.get("mock")).await;
+        assert!(waited.is_err());
+        assert_eq!(connections.status("mock"), ConnectionStatus::Connecting);
+
+        // Nobody waits for it anymore, it still finishes
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(connections.status("mock"), ConnectionStatus::Connected);
+
+        assert!(connections.get("mock").await.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,786 @@
+use crate::backend::{DataInfo, DataStream, DownloadBackend};
+use crate::connection::ConnectionManager;
+use crate::filetype::{self, SNIFF_LEN};
+use crate::retry::RetryPolicy;
+use crate::server::ServerError;
+use crate::{DownloadEvent, ResolvedPointer, ScratchpadInfo};
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, watch};
+
+pub struct DownloadJob {
+    pub id: String,
+    pub address: String,
+    pub environment: String,
+    pub save_path: PathBuf,
+    pub resume_from: usize,
+    pub retry_policy: RetryPolicy,
+    pub parallel_chunks: usize,
+    pub conflict_policy: ConflictPolicy,
+    // Target of the pointer the partial file was downloaded from, kept so a resumed download
+    // doesn't mix two versions
+    pub resolved_pointer: Option<ResolvedPointer>,
+    // Version of the scratchpad the partial file was downloaded from, for the same reason
+    pub scratchpad: Option<ScratchpadInfo>,
+}
+
+// What to do when the file to save to already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    Overwrite,
+    #[default]
+    Rename,
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub enum DownloadError {
+    Server(ServerError),
+    File(String),
+    // The download was dropped while paused, e.g. cancelled
+    Abandoned,
+}
+
+impl DownloadError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DownloadError::Server(error) => error.is_transient(),
+            DownloadError::File(_) | DownloadError::Abandoned => false,
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Server(error) => write!(f, "{error}"),
+            DownloadError::File(message) => write!(f, "{message}"),
+            DownloadError::Abandoned => write!(f, "Download abandoned while paused"),
+        }
+    }
+}
+
+pub async fn run_download<B: DownloadBackend>(
+    job: DownloadJob,
+    connections: ConnectionManager<B>,
+    tx: mpsc::UnboundedSender<DownloadEvent>,
+    mut pause_rx: watch::Receiver<bool>,
+) {
+    let mut written = job.resume_from;
+    let mut resolved = job.resolved_pointer.clone();
+    let mut scratchpad = job.scratchpad.clone();
+    let mut attempt = 1;
+
+    loop {
+        let result = download_attempt(
+            &job,
+            &connections,
+            &tx,
+            &mut pause_rx,
+            &mut written,
+            &mut resolved,
+            &mut scratchpad,
+        )
+        .await;
+
+        match result {
+            // The app is gone, leave the partial file for next time
+            Ok(()) if tx.is_closed() => return,
+            Ok(()) => {
+                let event = match finish_file(&job).await {
+                    Ok(path) => DownloadEvent::Completed { id: job.id, path },
+                    Err(error) => DownloadEvent::Error { id: job.id, error },
+                };
+                let _ = tx.send(event);
+                return;
+            }
+            Err(error) if error.is_transient() && attempt < job.retry_policy.max_attempts => {
+                let delay = job.retry_policy.delay(attempt);
+                attempt += 1;
+                eprintln!("Download {} failed, retrying in {delay:?}: {error}", job.id);
+
+                let _ = tx.send(DownloadEvent::Retrying {
+                    id: job.id.clone(),
+                    attempt,
+                    error: error.to_string(),
+                });
+                // Resuming a download paused while waiting retries it right away
+                if unless_paused(&mut pause_rx, tokio::time::sleep(delay))
+                    .await
+                    .is_none()
+                {
+                    if let Err(error) = wait_while_paused(&job, &tx, &mut pause_rx).await {
+                        let _ = tx.send(DownloadEvent::Error { id: job.id, error });
+                        return;
+                    }
+                }
+            }
+            Err(error) => {
+                let _ = tx.send(DownloadEvent::Error { id: job.id, error });
+                return;
+            }
+        }
+    }
+}
+
+// One try at the download, continuing after the `written` bytes of earlier tries
+async fn download_attempt<B: DownloadBackend>(
+    job: &DownloadJob,
+    connections: &ConnectionManager<B>,
+    tx: &mpsc::UnboundedSender<DownloadEvent>,
+    pause_rx: &mut watch::Receiver<bool>,
+    written: &mut usize,
+    resolved: &mut Option<ResolvedPointer>,
+    scratchpad: &mut Option<ScratchpadInfo>,
+) -> Result<(), DownloadError> {
+    // Get the shared backend for this environment (bootstraps on first use)
+    let backend = loop {
+        match unless_paused(pause_rx, connections.get(&job.environment)).await {
+            Some(backend) => break backend.map_err(DownloadError::Server)?,
+            None => wait_while_paused(job, tx, pause_rx).await?,
+        }
+    };
+
+    // Pointers are resolved once, later tries stick to the same target
+    if resolved.is_none() {
+        *resolved = loop {
+            match unless_paused(pause_rx, backend.resolve(&job.address)).await {
+                Some(pointer) => break pointer.map_err(DownloadError::Server)?,
+                None => wait_while_paused(job, tx, pause_rx).await?,
+            }
+        };
+        if let Some(pointer) = resolved {
+            let _ = tx.send(DownloadEvent::PointerResolved {
+                id: job.id.clone(),
+                pointer: pointer.clone(),
+            });
+        }
+    }
+    let address = resolved
+        .as_ref()
+        .map_or(job.address.as_str(), |pointer| &pointer.target);
+
+    // The datamap is fetched once per attempt, for the total size and every stream after it
+    let (info, data) = loop {
+        match unless_paused(pause_rx, backend.open(address)).await {
+            Some(opened) => break opened.map_err(DownloadError::Server)?,
+            None => wait_while_paused(job, tx, pause_rx).await?,
+        }
+    };
+    let DataInfo {
+        size,
+        scratchpad: fetched,
+        content,
+    } = info;
+    if let Some(fetched) = fetched {
+        // The bytes on disk belong to an older version, they can't be continued
+        if scratchpad
+            .as_ref()
+            .is_some_and(|previous| previous.counter != fetched.counter)
+        {
+            eprintln!("Scratchpad {address} changed, starting {} over", job.id);
+            *written = 0;
+        }
+        let _ = tx.send(DownloadEvent::ScratchpadFetched {
+            id: job.id.clone(),
+            scratchpad: fetched.clone(),
+        });
+        *scratchpad = Some(fetched);
+    }
+
+    // Create the partial file, or reopen it when resuming
+    let part = part_path(&job.save_path);
+    let (mut file, offset) = open_save_file(&part, *written)
+        .await
+        .map_err(|e| DownloadError::File(format!("Failed to create save file: {e}")))?;
+    *written = offset;
+
+    let _ = tx.send(DownloadEvent::Started {
+        id: job.id.clone(),
+        offset,
+        total_size: size,
+    });
+
+    // The start of the file, to tell its type once enough of it arrived. When resuming, the
+    // earlier attempts already wrote some or all of it.
+    let mut head = if offset == 0 {
+        Some(Vec::with_capacity(SNIFF_LEN))
+    } else {
+        let bytes = read_head(&part, offset.min(SNIFF_LEN))
+            .await
+            .map_err(|e| DownloadError::File(format!("Failed to read partial file: {e}")))?;
+        if bytes.len() == SNIFF_LEN {
+            send_file_type(tx, &job.id, &bytes);
+            None
+        } else {
+            Some(bytes)
+        }
+    };
+
+    loop {
+        // Start downloading, skipping the bytes we already have
+        let mut stream: DataStream = match &content {
+            Some(content) => {
+                let rest = (*written < content.len()).then(|| Ok(content.slice(*written..)));
+                futures::stream::iter(rest).boxed()
+            }
+            None => backend
+                .stream(&data, *written, job.parallel_chunks)
+                .await
+                .map_err(DownloadError::Server)?,
+        };
+
+        let paused = loop {
+            let chunk_result = tokio::select! {
+                // Pausing wins over a chunk arriving at the same time
+                biased;
+                Ok(_) = pause_rx.wait_for(|paused| *paused) => break true,
+                chunk_result = stream.next() => chunk_result,
+            };
+            let Some(chunk_result) = chunk_result else {
+                break false;
+            };
+            let chunk = chunk_result.map_err(DownloadError::Server)?;
+
+            // Write chunk directly to save file, and make sure it landed before counting it
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| DownloadError::File(format!("Failed to write file: {e}")))?;
+            file.flush()
+                .await
+                .map_err(|e| DownloadError::File(format!("Failed to flush file: {e}")))?;
+            *written += chunk.len();
+
+            if let Some(bytes) = &mut head {
+                bytes.extend_from_slice(&chunk[..chunk.len().min(SNIFF_LEN - bytes.len())]);
+                if bytes.len() == SNIFF_LEN {
+                    send_file_type(tx, &job.id, bytes);
+                    head = None;
+                }
+            }
+
+            if tx
+                .send(DownloadEvent::ChunkReceived {
+                    id: job.id.clone(),
+                    size: chunk.len(),
+                })
+                .is_err()
+            {
+                // The app is gone, nobody is waiting for this download anymore
+                return Ok(());
+            }
+        };
+        if !paused {
+            break;
+        }
+
+        // Abandon the requests in flight, the next stream picks up after the written bytes
+        drop(stream);
+        wait_while_paused(job, tx, pause_rx).await?;
+    }
+
+    // Make sure everything is on disk before the file gets its final name
+    file.sync_all()
+        .await
+        .map_err(|e| DownloadError::File(format!("Failed to sync file: {e}")))?;
+
+    // Files shorter than the sniffing window
+    if let Some(bytes) = &head {
+        if *written >= size {
+            send_file_type(tx, &job.id, bytes);
+        }
+    }
+
+    // A stream that ends early is a network hiccup, the next attempt picks up from here
+    if *written < size {
+        return Err(DownloadError::Server(ServerError::Network(format!(
+            "Stream ended after {} of {size} bytes",
+            *written
+        ))));
+    }
+    if *written > size {
+        return Err(DownloadError::Server(ServerError::Data(format!(
+            "Received {} bytes, expected {size}",
+            *written
+        ))));
+    }
+
+    Ok(())
+}
+
+// Run `future` to the end, or give up on it as soon as the download is paused
+async fn unless_paused<T>(
+    pause_rx: &mut watch::Receiver<bool>,
+    future: impl Future<Output = T>,
+) -> Option<T> {
+    tokio::select! {
+        biased;
+        Ok(_) = pause_rx.wait_for(|paused| *paused) => None,
+        output = future => Some(output),
+    }
+}
+
+async fn wait_while_paused(
+    job: &DownloadJob,
+    tx: &mpsc::UnboundedSender<DownloadEvent>,
+    pause_rx: &mut watch::Receiver<bool>,
+) -> Result<(), DownloadError> {
+    let _ = tx.send(DownloadEvent::Paused { id: job.id.clone() });
+    if pause_rx.wait_for(|paused| !*paused).await.is_err() {
+        return Err(DownloadError::Abandoned);
+    }
+    let _ = tx.send(DownloadEvent::Resumed { id: job.id.clone() });
+    Ok(())
+}
+
+// Downloads are written next to their final path until they complete
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+// `name (1).ext`, `name (2).ext`... whichever is free first
+pub fn unique_path(path: &Path, is_taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut n = 1;
+    loop {
+        let candidate = path.with_file_name(format!("{stem} ({n}){extension}"));
+        if !candidate.exists() && !is_taken(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+// Move the complete partial file to its final path
+async fn finish_file(job: &DownloadJob) -> Result<PathBuf, DownloadError> {
+    let target = match job.conflict_policy {
+        ConflictPolicy::Overwrite => job.save_path.clone(),
+        // Something else took the name while downloading, never overwrite it
+        _ if job.save_path.exists() => unique_path(&job.save_path, |_| false),
+        _ => job.save_path.clone(),
+    };
+
+    tokio::fs::rename(part_path(&job.save_path), &target)
+        .await
+        .map_err(|e| DownloadError::File(format!("Failed to move file into place: {e}")))?;
+
+    Ok(target)
+}
+
+fn send_file_type(tx: &mpsc::UnboundedSender<DownloadEvent>, id: &str, head: &[u8]) {
+    if let Some(file_type) = filetype::detect(head) {
+        let _ = tx.send(DownloadEvent::FileTypeDetected {
+            id: id.to_string(),
+            file_type,
+        });
+    }
+}
+
+async fn read_head(path: &Path, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(len);
+    tokio::fs::File::open(path)
+        .await?
+        .take(len as u64)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(head)
+}
+
+async fn open_save_file(
+    path: &Path,
+    resume_from: usize,
+) -> std::io::Result<(tokio::fs::File, usize)> {
+    // Files from archives are saved in their own subdirectories
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    if resume_from == 0 {
+        return tokio::fs::File::create(path).await.map(|file| (file, 0));
+    }
+
+    // Trust whichever is shorter: the bytes on disk or the bytes we recorded
+    let file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    let offset = (file.metadata().await?.len() as usize).min(resume_from);
+    file.set_len(offset as u64).await?;
+
+    Ok((file, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{connections, job, temp_path, Fault, MockBackend, ADDRESS, CONTENT};
+    use std::time::Duration;
+
+    async fn run(mock: &MockBackend, job: DownloadJob) -> Vec<DownloadEvent> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (_pause_tx, pause_rx) = watch::channel(false);
+        run_download(job, connections(mock), tx, pause_rx).await;
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn downloads_whole_file() {
+        let mock = MockBackend::new().with_file(ADDRESS, CONTENT);
+        let path = temp_path("whole");
+
+        let events = run(&mock, job(path.clone())).await;
+
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Completed { .. })
+        ));
+        let received: usize = events
+            .iter()
+            .map(|event| match event {
+                DownloadEvent::ChunkReceived { size, .. } => *size,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(received, CONTENT.len());
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_chunk_error_from_offset() {
+        let mock = MockBackend::new()
+            .with_file(ADDRESS, CONTENT)
+            .with_fault(Fault::ChunkError {
+                after_chunks: 2,
+                transient: true,
+            });
+        let path = temp_path("transient");
+
+        let events = run(&mock, job(path.clone())).await;
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, DownloadEvent::Retrying { attempt: 2, .. })));
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Completed { .. })
+        ));
+        assert_eq!(mock.requested_offsets(), vec![0, 8]);
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_fatal_error() {
+        let mock = MockBackend::new()
+            .with_file(ADDRESS, CONTENT)
+            .with_fault(Fault::ChunkError {
+                after_chunks: 1,
+                transient: false,
+            });
+        let path = temp_path("fatal");
+
+        let events = run(&mock, job(path.clone())).await;
+
+        assert!(matches!(events.last(), Some(DownloadEvent::Error { .. })));
+        assert_eq!(mock.requested_offsets(), vec![0]);
+        // Only the partial file is left, never a truncated file under the final name
+        assert!(!path.exists());
+        assert!(part_path(&path).exists());
+        let _ = std::fs::remove_file(part_path(&path));
+    }
+
+    #[tokio::test]
+    async fn resumes_truncated_stream() {
+        let mock = MockBackend::new()
+            .with_file(ADDRESS, CONTENT)
+            .with_fault(Fault::Truncate { after_chunks: 3 });
+        let path = temp_path("truncated");
+
+        let events = run(&mock, job(path.clone())).await;
+
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Completed { .. })
+        ));
+        assert_eq!(mock.requested_offsets(), vec![0, 12]);
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let fault = Fault::StreamError { transient: true };
+        let mock = MockBackend::new()
+            .with_file(ADDRESS, CONTENT)
+            .with_fault(fault)
+            .with_fault(fault)
+            .with_fault(fault);
+        let path = temp_path("give_up");
+
+        let events = run(&mock, job(path.clone())).await;
+
+        assert!(matches!(events.last(), Some(DownloadEvent::Error { .. })));
+        assert_eq!(mock.requested_offsets().len(), 3);
+        let _ = std::fs::remove_file(part_path(&path));
+    }
+
+    #[tokio::test]
+    async fn keeps_existing_file_on_conflict() {
+        let mock = MockBackend::new().with_file(ADDRESS, CONTENT);
+        let path = temp_path("conflict.txt");
+        std::fs::write(&path, b"keep me").unwrap();
+
+        let events = run(&mock, job(path.clone())).await;
+
+        let renamed = path.with_file_name(format!(
+            "ant_download_test_{}_conflict (1).txt",
+            std::process::id()
+        ));
+        assert!(
+            matches!(events.last(), Some(DownloadEvent::Completed { path, .. }) if *path == renamed)
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep me");
+        assert_eq!(std::fs::read(&renamed).unwrap(), CONTENT);
+        assert!(!part_path(&path).exists());
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(renamed);
+    }
+
+    #[tokio::test]
+    async fn pauses_during_slow_chunk_and_resumes_from_offset() {
+        let content = b"0123456789abcdef";
+        let mock = MockBackend::new()
+            .with_file(ADDRESS, content)
+            .with_latency(Duration::from_millis(200));
+        let path = temp_path("pause");
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (pause_tx, pause_rx) = watch::channel(false);
+        let task = tokio::spawn(run_download(
+            job(path.clone()),
+            connections(&mock),
+            tx,
+            pause_rx,
+        ));
+
+        // Pause while the second chunk is still being fetched
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        pause_tx.send_replace(true);
+        let paused = tokio::time::timeout(Duration::from_millis(100), async {
+            while let Some(event) = rx.recv().await {
+                if matches!(event, DownloadEvent::Paused { .. }) {
+                    return true;
+                }
+            }
+            false
+        })
+        .await;
+        assert_eq!(paused, Ok(true));
+
+        pause_tx.send_replace(false);
+        task.await.unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, DownloadEvent::Resumed { .. })));
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Completed { .. })
+        ));
+        assert_eq!(mock.requested_offsets(), vec![0, 4]);
+        assert_eq!(std::fs::read(&path).unwrap(), content);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn resuming_during_retry_backoff_retries_right_away() {
+        let mock = MockBackend::new()
+            .with_file(ADDRESS, CONTENT)
+            .with_fault(Fault::StreamError { transient: true });
+        let path = temp_path("pause_backoff");
+        let mut job = job(path.clone());
+        job.retry_policy.initial_delay_secs = 60;
+        job.retry_policy.max_delay_secs = 60;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (pause_tx, pause_rx) = watch::channel(false);
+        let task = tokio::spawn(run_download(job, connections(&mock), tx, pause_rx));
+
+        // Pause once the first attempt failed and the retry is waiting
+        loop {
+            match rx.recv().await {
+                Some(DownloadEvent::Retrying { .. }) => break,
+                Some(_) => {}
+                None => panic!("download ended before retrying"),
+            }
+        }
+        pause_tx.send_replace(true);
+        loop {
+            match rx.recv().await {
+                Some(DownloadEvent::Paused { .. }) => break,
+                Some(_) => {}
+                None => panic!("download ended before pausing"),
+            }
+        }
+
+        pause_tx.send_replace(false);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("the retry should not wait out its delay")
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Completed { .. })
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn follows_pointer_and_keeps_its_target_when_resuming() {
+        let pointer = "pointer:".to_string() + &"c".repeat(96);
+        let new_address = "b".repeat(64);
+        let new_content = b"a newer version of the file".as_slice();
+        let mock = MockBackend::new()
+            .with_file(ADDRESS, CONTENT)
+            .with_file(&new_address, new_content)
+            .with_pointer(&pointer, ADDRESS, 7);
+        let path = temp_path("pointer");
+
+        let mut pointer_job = job(path.clone());
+        pointer_job.address = pointer.clone();
+        let events = run(&mock, pointer_job).await;
+
+        let resolved = events.iter().find_map(|event| match event {
+            DownloadEvent::PointerResolved { pointer, .. } => Some(pointer.clone()),
+            _ => None,
+        });
+        assert_eq!(
+            resolved,
+            Some(ResolvedPointer {
+                target: ADDRESS.to_string(),
+                counter: 7,
+            })
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(&path);
+
+        // A partial file of the old target is finished from that target, not the new one
+        mock.update_pointer(&pointer, &new_address);
+        std::fs::write(part_path(&path), &CONTENT[..8]).unwrap();
+        let mut resumed_job = job(path.clone());
+        resumed_job.address = pointer;
+        resumed_job.resume_from = 8;
+        resumed_job.resolved_pointer = resolved;
+        let events = run(&mock, resumed_job).await;
+
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, DownloadEvent::PointerResolved { .. })));
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn detects_type_of_resumed_download_from_partial_file() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let mock = MockBackend::new().with_file(ADDRESS, png);
+        let path = temp_path("resumed_type");
+
+        // The signature was written by an earlier run
+        std::fs::write(part_path(&path), &png[..6]).unwrap();
+        let mut resumed_job = job(path.clone());
+        resumed_job.resume_from = 6;
+        let events = run(&mock, resumed_job).await;
+
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DownloadEvent::FileTypeDetected { file_type, .. } if file_type.extension == "png"
+        )));
+        assert_eq!(mock.requested_offsets(), vec![6]);
+        assert_eq!(std::fs::read(&path).unwrap(), png);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn saves_scratchpad_payload() {
+        let scratchpad = "scratchpad:".to_string() + &"d".repeat(96);
+        let mock = MockBackend::new().with_scratchpad(&scratchpad, CONTENT, 12);
+        let path = temp_path("scratchpad");
+
+        let mut scratchpad_job = job(path.clone());
+        scratchpad_job.address = scratchpad;
+        let events = run(&mock, scratchpad_job).await;
+
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DownloadEvent::ScratchpadFetched { scratchpad, .. } if scratchpad.counter == 12
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Completed { .. })
+        ));
+        // Saved from the fetched payload, not from a second request
+        assert!(mock.requested_offsets().is_empty());
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn starts_over_when_scratchpad_changed_since_pausing() {
+        let scratchpad = "scratchpad:".to_string() + &"e".repeat(96);
+        let mock = MockBackend::new().with_scratchpad(&scratchpad, CONTENT, 12);
+        let path = temp_path("scratchpad_changed");
+
+        // Half of version 12 is on disk, the owner wrote version 13 since
+        std::fs::write(part_path(&path), &CONTENT[..8]).unwrap();
+        let new_content = b"a completely different payload";
+        mock.update_scratchpad(&scratchpad, new_content);
+
+        let mut resumed_job = job(path.clone());
+        resumed_job.address = scratchpad;
+        resumed_job.resume_from = 8;
+        resumed_job.scratchpad = Some(ScratchpadInfo {
+            counter: 12,
+            data_encoding: 0,
+        });
+        let events = run(&mock, resumed_job).await;
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, DownloadEvent::Started { offset: 0, .. })));
+        assert!(matches!(
+            events.last(),
+            Some(DownloadEvent::Completed { .. })
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), new_content);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+
+// Enough of the start of a file for every signature below (tar has its magic at 257)
+pub const SNIFF_LEN: usize = 512;
+
+#[derive(Debug, PartialEq)]
+pub struct FileType {
+    pub extension: &'static str,
+    pub name: &'static str,
+    // Other extensions for the same content, e.g. office documents are zip files
+    pub aliases: &'static [&'static str],
+}
+
+struct Signature {
+    offset: usize,
+    magic: &'static [u8],
+    // Further check of the head, for magic numbers too short to trust on their own
+    check: fn(&[u8]) -> bool,
+    file_type: FileType,
+}
+
+const fn signature(
+    offset: usize,
+    magic: &'static [u8],
+    extension: &'static str,
+    name: &'static str,
+    aliases: &'static [&'static str],
+) -> Signature {
+    Signature {
+        offset,
+        magic,
+        check: |_| true,
+        file_type: FileType {
+            extension,
+            name,
+            aliases,
+        },
+    }
+}
+
+const fn checked(signature: Signature, check: fn(&[u8]) -> bool) -> Signature {
+    Signature { check, ..signature }
+}
+
+// Checked in order, so more specific signatures come first
+const SIGNATURES: &[Signature] = &[
+    signature(0, b"\x89PNG\r\n\x1a\n", "png", "PNG image", &[]),
+    signature(0, b"\xff\xd8\xff", "jpg", "JPEG image", &["jpeg"]),
+    signature(0, b"GIF87a", "gif", "GIF image", &[]),
+    signature(0, b"GIF89a", "gif", "GIF image", &[]),
+    signature(8, b"WEBP", "webp", "WebP image", &[]),
+    signature(8, b"WAVE", "wav", "WAV audio", &[]),
+    signature(8, b"AVI ", "avi", "AVI video", &[]),
+    signature(0, b"%PDF-", "pdf", "PDF document", &[]),
+    signature(
+        0,
+        b"PK\x03\x04",
+        "zip",
+        "ZIP archive",
+        &[
+            "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "ipa",
+        ],
+    ),
+    signature(0, b"PK\x05\x06", "zip", "ZIP archive", &[]),
+    signature(0, b"\x1f\x8b", "gz", "Gzip archive", &["tgz"]),
+    signature(0, b"BZh", "bz2", "Bzip2 archive", &["tbz2"]),
+    signature(0, b"\xfd7zXZ\x00", "xz", "XZ archive", &["txz"]),
+    signature(0, b"\x28\xb5\x2f\xfd", "zst", "Zstandard archive", &[]),
+    signature(0, b"7z\xbc\xaf\x27\x1c", "7z", "7-Zip archive", &[]),
+    signature(0, b"Rar!\x1a\x07", "rar", "RAR archive", &[]),
+    signature(257, b"ustar", "tar", "Tar archive", &[]),
+    signature(4, b"ftypqt", "mov", "QuickTime video", &[]),
+    signature(4, b"ftypM4A", "m4a", "MPEG-4 audio", &[]),
+    signature(
+        4,
+        b"ftyp",
+        "mp4",
+        "MPEG-4 video",
+        &["m4v", "mov", "m4a", "3gp"],
+    ),
+    signature(
+        0,
+        b"\x1a\x45\xdf\xa3",
+        "mkv",
+        "Matroska video",
+        &["webm", "mka"],
+    ),
+    signature(0, b"ID3", "mp3", "MP3 audio", &[]),
+    checked(
+        signature(0, b"\xff\xfb", "mp3", "MP3 audio", &[]),
+        is_mp3_frame,
+    ),
+    signature(0, b"fLaC", "flac", "FLAC audio", &[]),
+    signature(0, b"OggS", "ogg", "Ogg media", &["oga", "ogv", "opus"]),
+    signature(
+        0,
+        b"SQLite format 3\x00",
+        "sqlite",
+        "SQLite database",
+        &["db"],
+    ),
+    signature(0, b"\x7fELF", "elf", "Linux executable", &["so", "bin"]),
+    checked(
+        signature(0, b"MZ", "exe", "Windows executable", &["dll"]),
+        is_portable_executable,
+    ),
+    signature(0, b"\x00asm", "wasm", "WebAssembly module", &[]),
+];
+
+// Guess the type of a file from its first bytes
+pub fn detect(head: &[u8]) -> Option<&'static FileType> {
+    SIGNATURES
+        .iter()
+        .find(|signature| {
+            head.get(signature.offset..signature.offset + signature.magic.len())
+                == Some(signature.magic)
+                && (signature.check)(head)
+        })
+        .map(|signature| &signature.file_type)
+}
+
+// A DOS header pointing to a PE header, "MZ" alone is common in text
+fn is_portable_executable(head: &[u8]) -> bool {
+    let Some(pe_offset) = head.get(0x3c..0x40) else {
+        return false;
+    };
+    let pe_offset = u32::from_le_bytes(pe_offset.try_into().unwrap()) as usize;
+    head.get(pe_offset..pe_offset + 4) == Some(b"PE\0\0")
+}
+
+// An MPEG audio frame header with a valid bitrate and sample rate
+fn is_mp3_frame(head: &[u8]) -> bool {
+    let Some(&flags) = head.get(2) else {
+        return false;
+    };
+    let bitrate = flags >> 4;
+    let sample_rate = (flags >> 2) & 0b11;
+    bitrate != 0 && bitrate != 0b1111 && sample_rate != 0b11
+}
+
+pub fn from_extension(extension: &str) -> Option<&'static FileType> {
+    SIGNATURES
+        .iter()
+        .map(|signature| &signature.file_type)
+        .find(|file_type| file_type.extension == extension)
+}
+
+impl FileType {
+    // Whether the path already has a fitting extension for this type
+    pub fn matches(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        extension == self.extension || self.aliases.contains(&extension.as_str())
+    }
+
+    pub fn rename(&self, path: &Path) -> PathBuf {
+        path.with_extension(self.extension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_common_types() {
+        let detect_extension = |head: &[u8]| detect(head).map(|file_type| file_type.extension);
+
+        assert_eq!(
+            detect_extension(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some("png")
+        );
+        assert_eq!(detect_extension(b"%PDF-1.7\n"), Some("pdf"));
+        assert_eq!(detect_extension(b"PK\x03\x04\x14\0"), Some("zip"));
+        assert_eq!(
+            detect_extension(b"\0\0\0\x20ftypisom\0\0\x02\0"),
+            Some("mp4")
+        );
+        assert_eq!(detect_extension(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(detect_extension(b"hello world"), None);
+        assert_eq!(detect_extension(b"MZ is not always a program"), None);
+        assert_eq!(detect_extension(b"\xff\xfb\x90\x64"), Some("mp3"));
+        assert_eq!(detect_extension(b"\xff\xfb\xff\xff"), None);
+
+        let mut exe = vec![0; 0x84];
+        exe[..2].copy_from_slice(b"MZ");
+        exe[0x3c] = 0x80;
+        exe[0x80..].copy_from_slice(b"PE\0\0");
+        assert_eq!(detect_extension(&exe), Some("exe"));
+        assert_eq!(detect_extension(b""), None);
+
+        let mut tar = vec![0; SNIFF_LEN];
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert_eq!(detect_extension(&tar), Some("tar"));
+    }
+
+    #[test]
+    fn matches_extension_and_aliases() {
+        let zip = from_extension("zip").unwrap();
+        assert!(zip.matches(Path::new("photos.ZIP")));
+        assert!(zip.matches(Path::new("report.docx")));
+        assert!(!zip.matches(Path::new("download_0123456789ab.ant")));
+        assert_eq!(
+            zip.rename(Path::new("dir/download_0123456789ab.ant")),
+            PathBuf::from("dir/download_0123456789ab.zip")
+        );
+    }
+}
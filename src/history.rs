@@ -0,0 +1,54 @@
+use crate::{DownloadItem, DownloadState, Verification};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const STATE_FILE: &str = "downloads.json";
+
+fn state_file() -> Option<PathBuf> {
+    dirs_next::data_dir().map(|dir| dir.join("ant_download").join(STATE_FILE))
+}
+
+pub fn load() -> HashMap<String, DownloadItem> {
+    let Some(path) = state_file() else {
+        return HashMap::new();
+    };
+
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(_) => return HashMap::new(), // No history yet
+    };
+
+    let mut downloads: HashMap<String, DownloadItem> = match serde_json::from_slice(&bytes) {
+        Ok(downloads) => downloads,
+        Err(e) => {
+            eprintln!("Ignoring unreadable download history at {path:?}: {e}");
+            return HashMap::new();
+        }
+    };
+
+    // Downloads that were still running when the app closed have no task anymore
+    for download in downloads.values_mut() {
+        if matches!(
+            download.status.state,
+            DownloadState::Waiting
+                | DownloadState::Downloading
+                | DownloadState::Paused
+                | DownloadState::Retrying(_)
+        ) {
+            download.status.state = DownloadState::Interrupted;
+        }
+
+        // Verification runs in the download manager, it was cut short too
+        if download.verification == Some(Verification::Verifying) {
+            download.verification = None;
+        }
+    }
+
+    downloads
+}
+
+pub fn save(downloads: &HashMap<String, DownloadItem>) -> Result<(), String> {
+    let path = state_file().ok_or("Could not find a data directory for download history")?;
+    crate::save_json(&path, downloads, "download history")
+}
@@ -0,0 +1,121 @@
+use eframe::egui;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+
+// Later launches of the app hand their links to the first one over this socket, so they open
+// in the window that is already connected instead of bootstrapping another client
+const SOCKET_FILE: &str = "instance.sock";
+
+pub enum Instance {
+    // A running app took the links
+    Forwarded,
+    // No app was running, this one listens for the links of later launches
+    Primary(UnixListener),
+}
+
+fn socket_path() -> Option<PathBuf> {
+    // Private to the user and cleaned up on logout, where there is one
+    dirs_next::runtime_dir()
+        .or_else(dirs_next::data_local_dir)
+        .map(|dir| dir.join("ant_download").join(SOCKET_FILE))
+}
+
+pub async fn forward_or_listen(links: &[String]) -> io::Result<Instance> {
+    let path = socket_path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "No directory for the instance socket",
+        )
+    })?;
+    forward_or_bind(&path, links).await
+}
+
+async fn forward_or_bind(path: &Path, links: &[String]) -> io::Result<Instance> {
+    match UnixStream::connect(path).await {
+        Ok(mut stream) => {
+            // One link per line
+            let message: String = links.iter().map(|link| format!("{link}\n")).collect();
+            stream.write_all(message.as_bytes()).await?;
+            stream.shutdown().await?;
+            return Ok(Instance::Forwarded);
+        }
+        // Nobody answered, the socket is left over from an app that didn't exit cleanly
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+        Err(_) => {}
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    UnixListener::bind(path).map(Instance::Primary)
+}
+
+// Pass the links of later launches on to the app and wake it up to open them
+pub fn listen(listener: UnixListener, links: mpsc::UnboundedSender<String>, ctx: egui::Context) {
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    println!("Stopped listening for links from other launches: {e}");
+                    return;
+                }
+            };
+
+            let links = links.clone();
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stream).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let link = line.trim();
+                    if !link.is_empty() {
+                        let _ = links.send(link.to_string());
+                    }
+                }
+                // Bring the window to the front, the links were opened to be seen
+                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                ctx.request_repaint();
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replaces_stale_socket() {
+        let dir = std::env::temp_dir().join(format!("ant_download_ipc_{}", std::process::id()));
+        let path = dir.join(SOCKET_FILE);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // A listener dropped without removing its socket, like after a crash
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let links = ["ant://abc".to_string()];
+        let Instance::Primary(listener) = forward_or_bind(&path, &links).await.unwrap() else {
+            panic!("stale socket taken for a running app");
+        };
+
+        // The new socket is live, later launches reach it
+        let forwarded = tokio::spawn(async move { forward_or_bind(&path, &links).await });
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(
+            lines.next_line().await.unwrap().as_deref(),
+            Some("ant://abc")
+        );
+        assert!(matches!(
+            forwarded.await.unwrap().unwrap(),
+            Instance::Forwarded
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
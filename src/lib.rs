@@ -0,0 +1,420 @@
+//! Download engine behind the Ant Download app: queueing, retries, resuming and
+//! verification of downloads from the Autonomi Network, usable without a window.
+
+pub mod address;
+pub mod backend;
+pub mod config;
+pub mod connection;
+mod download;
+pub mod filetype;
+pub mod history;
+mod manager;
+#[cfg(test)]
+mod mock;
+pub mod progress;
+pub mod queue;
+pub mod retry;
+pub mod server;
+pub mod verify;
+
+pub use download::{ConflictPolicy, DownloadError};
+pub use manager::DownloadManager;
+
+use filetype::FileType;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// Extension for files saved before their type is known, replaced once it is detected
+pub const PLACEHOLDER_EXTENSION: &str = "ant";
+
+// Links to data on the network look like `ant://<address>/<filename>`
+pub const URI_SCHEME: &str = "ant://";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DownloadState {
+    Waiting,
+    Downloading,
+    Paused,
+    Completed,
+    Error(String),
+    Retrying(String),
+    Interrupted,
+    Cancelled,
+    // The file already existed and the conflict policy said to leave it alone
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadStatus {
+    pub state: DownloadState,
+    pub total_bytes_received: usize,
+    pub chunks_received: usize,
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Verification {
+    Verifying,
+    Verified,
+    Mismatch,
+    Failed(String),
+}
+
+// Where a pointer address led when the download started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedPointer {
+    // Address of the data at the end of the pointer chain
+    pub target: String,
+    // Counter of the shared pointer, bumped by the publisher on every update
+    pub counter: u64,
+}
+
+// Version details of a downloaded scratchpad
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScratchpadInfo {
+    // Bumped by the owner on every update
+    pub counter: u64,
+    // Application defined format of the payload
+    pub data_encoding: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadItem {
+    pub address: String,
+    pub environment: String,
+    pub status: DownloadStatus,
+    pub save_path: Option<PathBuf>,
+    pub created_at: std::time::SystemTime,
+    #[serde(default)]
+    pub verification: Option<Verification>,
+    // Extension matching the content, see `filetype::detect`
+    #[serde(default)]
+    pub detected_type: Option<String>,
+    // Set when the address is a pointer, the downloaded data comes from its target
+    #[serde(default)]
+    pub resolved_pointer: Option<ResolvedPointer>,
+    #[serde(default)]
+    pub scratchpad: Option<ScratchpadInfo>,
+    // Size of the whole file, known once the download started
+    #[serde(default)]
+    pub total_size: usize,
+    // Overrides the manager's policy, e.g. for a file the user already agreed to replace
+    #[serde(default)]
+    pub conflict_policy: Option<ConflictPolicy>,
+}
+
+impl DownloadItem {
+    pub fn file_type(&self) -> Option<&'static FileType> {
+        filetype::from_extension(self.detected_type.as_deref()?)
+    }
+
+    // The detected type, when the file is saved under an extension that doesn't fit it
+    pub fn suggested_type(&self) -> Option<&'static FileType> {
+        let file_type = self.file_type()?;
+        let save_path = self.save_path.as_deref()?;
+        (!file_type.matches(save_path)).then_some(file_type)
+    }
+
+    // Fraction downloaded, when the total size is known
+    pub fn progress(&self) -> Option<f32> {
+        (self.total_size > 0)
+            .then(|| (self.status.total_bytes_received as f32 / self.total_size as f32).min(1.0))
+    }
+
+    pub fn has_placeholder_name(&self) -> bool {
+        self.save_path
+            .as_deref()
+            .and_then(Path::extension)
+            .is_some_and(|extension| extension == PLACEHOLDER_EXTENSION)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    PointerResolved {
+        id: String,
+        pointer: ResolvedPointer,
+    },
+    ScratchpadFetched {
+        id: String,
+        scratchpad: ScratchpadInfo,
+    },
+    Started {
+        id: String,
+        offset: usize,
+        total_size: usize,
+    },
+    ChunkReceived {
+        id: String,
+        size: usize,
+    },
+    FileTypeDetected {
+        id: String,
+        file_type: &'static FileType,
+    },
+    Completed {
+        id: String,
+        // Final path, which differs from the requested one after a name conflict
+        path: PathBuf,
+    },
+    Paused {
+        id: String,
+    },
+    Resumed {
+        id: String,
+    },
+    Error {
+        id: String,
+        error: DownloadError,
+    },
+    Retrying {
+        id: String,
+        attempt: u32,
+        error: String,
+    },
+    Verified {
+        id: String,
+        result: Result<bool, String>,
+    },
+}
+
+impl DownloadEvent {
+    pub fn id(&self) -> &str {
+        match self {
+            DownloadEvent::PointerResolved { id, .. }
+            | DownloadEvent::ScratchpadFetched { id, .. }
+            | DownloadEvent::Started { id, .. }
+            | DownloadEvent::ChunkReceived { id, .. }
+            | DownloadEvent::FileTypeDetected { id, .. }
+            | DownloadEvent::Completed { id, .. }
+            | DownloadEvent::Paused { id }
+            | DownloadEvent::Resumed { id }
+            | DownloadEvent::Error { id, .. }
+            | DownloadEvent::Retrying { id, .. }
+            | DownloadEvent::Verified { id, .. } => id,
+        }
+    }
+}
+
+// Split text into addresses, each optionally followed by an extension like `.pdf`. Links
+// come with a whole file name instead, see `default_filename`.
+pub fn parse_addresses(text: &str) -> Vec<(String, Option<String>)> {
+    let mut result = Vec::new();
+    let mut current_address: Option<String> = None;
+
+    for token in text.split(|c: char| c.is_whitespace() || c == ',' || c == ';') {
+        let token = token.trim();
+        if token.is_empty() {
+            continue;
+        }
+
+        // Links name their own file, they never take an extension
+        if let Some((address, filename)) = parse_uri(token) {
+            if let Some(addr) = current_address.take() {
+                result.push((addr, None));
+            }
+            result.push((address, filename));
+            continue;
+        }
+
+        // Check if this looks like an extension (starts with a dot)
+        if token.starts_with('.') {
+            // This is an extension for the previous address
+            if let Some(addr) = current_address.take() {
+                result.push((addr, Some(token.to_string())));
+            }
+        } else {
+            // This is a new address
+            // First, save the previous address if any (without extension)
+            if let Some(addr) = current_address.take() {
+                result.push((addr, None));
+            }
+            // Store this new address
+            current_address = Some(token.to_string());
+        }
+    }
+
+    // Don't forget the last address
+    if let Some(addr) = current_address {
+        result.push((addr, None));
+    }
+
+    result
+}
+
+// Address and file name of an `ant://<address>/<filename>` link, the file name is optional
+pub fn parse_uri(uri: &str) -> Option<(String, Option<String>)> {
+    let rest = uri.strip_prefix(URI_SCHEME)?;
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let (address, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if address.is_empty() {
+        return None;
+    }
+
+    // Only the last part of the path is used, nothing hidden or leaving the save folder
+    let filename =
+        path.rsplit('/').next().map(percent_decode).filter(|name| {
+            !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+        });
+
+    Some((address.to_string(), filename))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// `name` is an extension like `.pdf`, or the whole file name given by a link
+pub fn default_filename(address: &str, name: Option<&str>) -> String {
+    let prefix = address::hex_part(address)
+        .chars()
+        .take(12)
+        .collect::<String>();
+    match name {
+        Some(filename) if !filename.starts_with('.') => filename.to_string(),
+        Some(extension) => format!("download_{prefix}{extension}"),
+        None => format!("download_{prefix}.{PLACEHOLDER_EXTENSION}"),
+    }
+}
+
+// Versions of a graph are numbered in history order, the extension follows once the type is
+// detected
+pub fn version_filename(number: usize, content: &str) -> String {
+    let prefix = content.chars().take(12).collect::<String>();
+    format!("version_{number}_{prefix}.{PLACEHOLDER_EXTENSION}")
+}
+
+pub fn format_file_size(bytes: usize) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit_index = 0;
+
+    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit_index += 1;
+    }
+
+    if unit_index == 0 {
+        format!("{} {}", bytes, UNITS[unit_index])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit_index])
+    }
+}
+
+// Calendar date of a Unix timestamp, in UTC
+pub fn format_date(secs_since_epoch: u64) -> String {
+    // Civil date from the day count, after Howard Hinnant's `civil_from_days`
+    let days = secs_since_epoch / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+// Save `value` as JSON, `what` names it in errors. The JSON goes to a temporary file first so a
+// crash never leaves a half-written file behind.
+pub(crate) fn save_json(path: &Path, value: &impl Serialize, what: &str) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {what} directory: {e}"))?;
+    }
+
+    let json =
+        serde_json::to_vec_pretty(value).map_err(|e| format!("Failed to serialize {what}: {e}"))?;
+
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json).map_err(|e| format!("Failed to write {what}: {e}"))?;
+    std::fs::rename(&tmp_path, path).map_err(|e| format!("Failed to write {what}: {e}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_links_between_addresses() {
+        let addresses =
+            parse_addresses("abc .pdf ant://def/My%20Report.pdf ghi ant://jkl/ ant://mno/a/..");
+        assert_eq!(
+            addresses,
+            vec![
+                ("abc".to_string(), Some(".pdf".to_string())),
+                ("def".to_string(), Some("My Report.pdf".to_string())),
+                ("ghi".to_string(), None),
+                ("jkl".to_string(), None),
+                ("mno".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            default_filename("def", Some("My Report.pdf")),
+            "My Report.pdf"
+        );
+        assert_eq!(parse_uri("ant://"), None);
+        assert_eq!(
+            parse_uri("ant://abc/x%2F..%2Fy"),
+            Some(("abc".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_700_000_000), "2023-11-14");
+    }
+
+    #[test]
+    fn saves_json_without_leaving_temporary_file() {
+        let dir = std::env::temp_dir().join(format!("ant_download_json_{}", std::process::id()));
+        let path = dir.join("nested").join("saved.json");
+
+        save_json(&path, &vec![1, 2, 3], "numbers").unwrap();
+        save_json(&path, &vec![4], "numbers").unwrap();
+
+        let saved: Vec<u32> = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved, [4]);
+        assert!(!path.with_extension("json.tmp").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
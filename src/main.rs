@@ -1,128 +1,280 @@
-mod server;
-
-use server::Server;
-use server::{DEFAULT_ENVIRONMENT, ENVIRONMENTS};
+mod cli;
+#[cfg(unix)]
+mod ipc;
+
+use ant_download::address::AddressKind;
+use ant_download::backend::DownloadBackend;
+use ant_download::config::{self, Config, EnvironmentConfig, EvmNetworkConfig};
+use ant_download::connection::ConnectionStatus;
+use ant_download::server::{ArchiveEntry, GraphVersion, ServerError, DEFAULT_ENVIRONMENT};
+use ant_download::{
+    default_filename, format_date, format_duration, format_file_size, history, parse_addresses,
+    version_filename, ConflictPolicy, DownloadEvent, DownloadItem, DownloadManager, DownloadState,
+    ResolvedPointer, Verification, URI_SCHEME,
+};
 
 use eframe::egui;
-use std::collections::HashMap;
-use std::io::Write;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
-#[derive(Debug, Clone)]
-enum DownloadState {
-    Waiting,
-    Downloading,
-    Paused,
-    Completed,
-    Error(String),
-}
+// Progress is saved at most this often, state changes are saved right away
+const HISTORY_SAVE_INTERVAL: Duration = Duration::from_secs(2);
 
-#[derive(Debug, Clone)]
-struct DownloadStatus {
-    state: DownloadState,
-    total_bytes_received: usize,
-    chunks_received: usize,
+// Result of checking whether a single address is an archive, or the history of a graph entry
+struct AddressInspection {
+    address: String,
+    extension: Option<String>,
+    entries: Option<Vec<ArchiveEntry>>,
+    versions: Option<Result<Vec<GraphVersion>, ServerError>>,
+    // Target the checks above were made on, the download sticks to it
+    resolved_pointer: Option<ResolvedPointer>,
+    // Typed in the address field rather than opened as a link
+    from_input: bool,
 }
 
-#[derive(Debug, Clone)]
-struct DownloadItem {
+// Items listed behind one address, the user picks which ones to download
+struct Picker<T> {
     address: String,
-    status: DownloadStatus,
-    save_path: Option<std::path::PathBuf>,
-    file_size: usize,
-    created_at: std::time::SystemTime,
+    items: Vec<T>,
+    selected: Vec<bool>,
+    // Shown instead of the list when it could not be loaded
+    error: Option<String>,
+    from_input: bool,
+}
+
+type ArchivePicker = Picker<ArchiveEntry>;
+type VersionPicker = Picker<GraphVersion>;
+
+enum PickerAction {
+    None,
+    Download,
+    Close,
+}
+
+// Downloads waiting for confirmation because some of them were already downloaded
+struct DuplicatePrompt {
+    downloads: Vec<(String, PathBuf)>,
+    duplicates: Vec<bool>,
+    environment: String,
+    conflict_policy: Option<ConflictPolicy>,
+    resolved_pointer: Option<ResolvedPointer>,
+}
+
+// Networks being edited, saved to the config file only when the user asks
+struct NetworkEditor {
+    networks: Vec<NetworkDraft>,
+    selected: Option<usize>,
+    error: Option<String>,
+}
+
+// Text fields are kept as typed until saving
+struct NetworkDraft {
+    environment: EnvironmentConfig,
+    peers: String,
+    network_id: String,
+    bootstrap_cache_dir: String,
+}
+
+impl NetworkDraft {
+    fn new(environment: EnvironmentConfig) -> Self {
+        Self {
+            peers: environment.peers.join("\n"),
+            network_id: environment
+                .network_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            bootstrap_cache_dir: environment
+                .bootstrap_cache_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            environment,
+        }
+    }
+
+    fn to_environment(&self) -> Result<EnvironmentConfig, String> {
+        let network_id = match self.network_id.trim() {
+            "" => None,
+            id => Some(id.parse().map_err(|_| {
+                format!(
+                    "Network ID of {} must be a number from 0 to 255",
+                    self.environment.name
+                )
+            })?),
+        };
+        let bootstrap_cache_dir = match self.bootstrap_cache_dir.trim() {
+            "" => None,
+            dir => Some(PathBuf::from(dir)),
+        };
+
+        Ok(EnvironmentConfig {
+            name: self.environment.name.trim().to_string(),
+            peers: self
+                .peers
+                .lines()
+                .map(str::trim)
+                .filter(|peer| !peer.is_empty())
+                .map(str::to_string)
+                .collect(),
+            network_id,
+            bootstrap_cache_dir,
+            ..self.environment.clone()
+        })
+    }
 }
 
-enum DownloadEvent {
-    Started { id: String },
-    ChunkReceived { id: String, size: usize },
-    Completed { id: String },
-    Paused { id: String },
-    Resumed { id: String },
-    Error { id: String, error: String },
+enum DownloadAction {
+    Restart,
+    Cancel { delete_file: bool },
+    Remove { delete_file: bool },
+    MoveUp,
+    MoveDown,
+    MoveToFront,
+    Verify,
+    RenameToDetectedType,
 }
 
 struct AntDownloadApp {
     address_input: String,
     selected_env: String,
     is_connecting: bool,
-    downloads: HashMap<String, DownloadItem>,
-    download_receiver: mpsc::UnboundedReceiver<DownloadEvent>,
-    download_sender: mpsc::UnboundedSender<DownloadEvent>,
-    pause_senders: HashMap<String, mpsc::UnboundedSender<bool>>,
+    manager: DownloadManager,
+    inspect_receiver: mpsc::UnboundedReceiver<AddressInspection>,
+    inspect_sender: mpsc::UnboundedSender<AddressInspection>,
+    archive_picker: Option<ArchivePicker>,
+    version_picker: Option<VersionPicker>,
+    duplicate_prompt: Option<DuplicatePrompt>,
+    config: Config,
+    network_editor: Option<NetworkEditor>,
+    history_dirty: bool,
+    history_saved_at: Instant,
+    // `ant://` links from the command line and from later launches
+    link_receiver: mpsc::UnboundedReceiver<String>,
+    // Links wait here while another address is being checked or waits for an answer
+    pending_links: Vec<String>,
 }
 
-impl Default for AntDownloadApp {
-    fn default() -> Self {
-        let (tx, rx) = mpsc::unbounded_channel();
+impl AntDownloadApp {
+    fn new(link_receiver: mpsc::UnboundedReceiver<String>) -> Self {
+        let (inspect_tx, inspect_rx) = mpsc::unbounded_channel();
+        let mut manager = DownloadManager::default();
+        manager.restore(history::load());
         Self {
             address_input: String::new(),
             selected_env: DEFAULT_ENVIRONMENT.to_string(),
             is_connecting: false,
-            downloads: HashMap::new(),
-            download_receiver: rx,
-            download_sender: tx,
-            pause_senders: HashMap::new(),
+            manager,
+            inspect_receiver: inspect_rx,
+            inspect_sender: inspect_tx,
+            archive_picker: None,
+            version_picker: None,
+            duplicate_prompt: None,
+            config: config::load(),
+            network_editor: None,
+            history_dirty: false,
+            history_saved_at: Instant::now(),
+            link_receiver,
+            pending_links: Vec::new(),
         }
     }
 }
 
 impl eframe::App for AntDownloadApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let connection_status = self.manager.connections().status(&self.selected_env);
+
         // Request continuous repaints while any downloads are active
         if self.is_connecting
-            || self
-                .downloads
-                .values()
-                .any(|d| matches!(d.status.state, DownloadState::Downloading))
+            || connection_status == ConnectionStatus::Connecting
+            || self.manager.downloads().iter().any(|(id, d)| {
+                matches!(
+                    d.status.state,
+                    DownloadState::Downloading | DownloadState::Retrying(_)
+                ) || d.verification == Some(Verification::Verifying)
+                    // Still connecting, before the first bytes arrive
+                    || (matches!(d.status.state, DownloadState::Waiting)
+                        && self.manager.is_active(id))
+            })
         {
             ctx.request_repaint();
         }
 
-        // Process download events
-        while let Ok(event) = self.download_receiver.try_recv() {
-            match event {
-                DownloadEvent::Started { id } => {
-                    if let Some(download) = self.downloads.get_mut(&id) {
-                        download.status.state = DownloadState::Downloading;
-                    }
-                    self.is_connecting = false;
-                }
-                DownloadEvent::ChunkReceived { id, size } => {
-                    if let Some(download) = self.downloads.get_mut(&id) {
-                        download.status.chunks_received += 1;
-                        download.status.total_bytes_received += size;
-                        download.file_size += size;
-                    }
-                }
-                DownloadEvent::Completed { id } => {
-                    if let Some(download) = self.downloads.get_mut(&id) {
-                        download.status.state = DownloadState::Completed;
-                    }
-                    // Clean up pause sender
-                    self.pause_senders.remove(&id);
-                }
-                DownloadEvent::Paused { id } => {
-                    if let Some(download) = self.downloads.get_mut(&id) {
-                        download.status.state = DownloadState::Paused;
-                    }
-                }
-                DownloadEvent::Resumed { id } => {
-                    if let Some(download) = self.downloads.get_mut(&id) {
-                        download.status.state = DownloadState::Downloading;
-                    }
-                }
-                DownloadEvent::Error { id, error } => {
-                    if let Some(download) = self.downloads.get_mut(&id) {
-                        download.status.state = DownloadState::Error(error);
-                    }
-                    self.is_connecting = false;
-                    // Clean up pause sender
-                    self.pause_senders.remove(&id);
+        // Apply download events, this also starts queued downloads in freed slots
+        let events = self.manager.process_events();
+        if !events.is_empty() {
+            self.history_dirty = true;
+        }
+        let state_changed = events
+            .iter()
+            .any(|event| !matches!(event, DownloadEvent::ChunkReceived { .. }));
+
+        // Archives open the file picker, graph entries their versions, anything else asks where
+        // to save it
+        while let Ok(inspection) = self.inspect_receiver.try_recv() {
+            self.is_connecting = false;
+            if let Some(versions) = inspection.versions {
+                let (versions, error) = match versions {
+                    Ok(versions) => (versions, None),
+                    Err(error) => (
+                        Vec::new(),
+                        Some(format!("Could not load the history: {error}")),
+                    ),
+                };
+                self.version_picker = Some(VersionPicker {
+                    address: inspection.address,
+                    selected: vec![false; versions.len()],
+                    items: versions,
+                    error,
+                    from_input: inspection.from_input,
+                });
+                continue;
+            }
+            match inspection.entries {
+                Some(entries) => {
+                    self.archive_picker = Some(ArchivePicker {
+                        address: inspection.address,
+                        selected: vec![true; entries.len()],
+                        items: entries,
+                        error: None,
+                        from_input: inspection.from_input,
+                    });
                 }
+                None => self.save_single_download(
+                    inspection.address,
+                    inspection.extension,
+                    inspection.from_input,
+                    inspection.resolved_pointer,
+                ),
             }
         }
 
+        // Opened links are downloaded like a pasted address, but leave the address field alone
+        while let Ok(link) = self.link_receiver.try_recv() {
+            self.pending_links.push(link);
+        }
+        // Opening them now would replace the picker or prompt the user is answering
+        let is_busy = self.is_connecting
+            || self.archive_picker.is_some()
+            || self.version_picker.is_some()
+            || self.duplicate_prompt.is_some();
+        if !is_busy && !self.pending_links.is_empty() {
+            let links = std::mem::take(&mut self.pending_links);
+            self.download_addresses(parse_addresses(&links.join(" ")), false);
+        }
+
+        if self.history_dirty
+            && (state_changed || self.history_saved_at.elapsed() >= HISTORY_SAVE_INTERVAL)
+        {
+            self.save_history();
+        }
+
+        self.show_archive_picker(ctx);
+        self.show_version_picker(ctx);
+        self.show_duplicate_prompt(ctx);
+        self.show_network_editor(ctx);
+
         // Main UI
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical(|ui| {
@@ -134,15 +286,15 @@ impl eframe::App for AntDownloadApp {
                     let response = ui.add_sized(
                         [400.0, 22.0],
                         egui::TextEdit::singleline(&mut self.address_input)
-                            .hint_text("Enter file address..."),
+                            .hint_text("Enter file address, pointer or private datamap..."),
                     );
 
                     // Environment selector
                     egui::ComboBox::from_label("🌐")
                         .selected_text(&self.selected_env)
                         .show_ui(ui, |ui| {
-                            for env in ENVIRONMENTS {
-                                ui.selectable_value(&mut self.selected_env, env.to_string(), env);
+                            for env in self.config.environment_names() {
+                                ui.selectable_value(&mut self.selected_env, env.clone(), env);
                             }
                         });
 
@@ -152,6 +304,13 @@ impl eframe::App for AntDownloadApp {
                         if ui.button("Download").clicked() {
                             self.start_download();
                         }
+                        if ui
+                            .small_button("Verify file...")
+                            .on_hover_text("Check a file already on disk matches this address")
+                            .clicked()
+                        {
+                            self.verify_existing_file();
+                        }
                     });
 
                     // Status indicator
@@ -164,6 +323,12 @@ impl eframe::App for AntDownloadApp {
                         );
                     }
 
+                    // Download settings
+                    ui.menu_button("⚙", |ui| self.show_settings(ui));
+
+                    // Connection status for the selected environment
+                    self.show_connection_status(ui, &connection_status);
+
                     // Auto-focus on startup
                     if self.address_input.is_empty() {
                         response.request_focus();
@@ -179,70 +344,40 @@ impl eframe::App for AntDownloadApp {
             });
         });
     }
-}
-
-impl AntDownloadApp {
-    fn parse_addresses(text: &str) -> Vec<(String, Option<String>)> {
-        let mut result = Vec::new();
-        let mut current_address: Option<String> = None;
-
-        for token in text.split(|c: char| c.is_whitespace() || c == ',' || c == ';') {
-            let token = token.trim();
-            if token.is_empty() {
-                continue;
-            }
-
-            // Check if this looks like an extension (starts with a dot)
-            if token.starts_with('.') {
-                // This is an extension for the previous address
-                if let Some(addr) = current_address.take() {
-                    result.push((addr, Some(token.to_string())));
-                }
-            } else {
-                // This is a new address
-                // First, save the previous address if any (without extension)
-                if let Some(addr) = current_address.take() {
-                    result.push((addr, None));
-                }
-                // Store this new address
-                current_address = Some(token.to_string());
-            }
-        }
-
-        // Don't forget the last address
-        if let Some(addr) = current_address {
-            result.push((addr, None));
-        }
 
-        result
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_history();
     }
+}
 
+impl AntDownloadApp {
     fn start_download(&mut self) {
-        let addresses_with_extensions = Self::parse_addresses(&self.address_input);
+        let addresses = parse_addresses(&self.address_input);
+        self.download_addresses(addresses, true);
+    }
 
+    // The address field is cleared once the addresses typed there are on their way
+    fn download_addresses(
+        &mut self,
+        addresses_with_extensions: Vec<(String, Option<String>)>,
+        from_input: bool,
+    ) {
         match addresses_with_extensions.as_slice() {
             [] => return, // No addresses
+            [(address, extension)]
+                if matches!(
+                    AddressKind::detect(address),
+                    Some(AddressKind::Public | AddressKind::Pointer | AddressKind::GraphEntry)
+                ) =>
+            {
+                // Public addresses and pointers may be archives and graph entries list their
+                // versions, check before asking where to save
+                self.inspect_address(address.clone(), extension.clone(), from_input);
+                return;
+            }
             [(address, extension)] => {
-                // Single address: use file picker
-                let mut dialog = rfd::FileDialog::new()
-                    .set_title("Save Downloaded File As");
-
-                // Pre-fill filename with extension if provided
-                if let Some(ext) = extension {
-                    let filename = format!(
-                        "download_{}{}",
-                        address.chars().take(12).collect::<String>(),
-                        ext
-                    );
-                    dialog = dialog.set_file_name(&filename);
-                }
-
-                let save_path = match dialog.save_file() {
-                    Some(path) => path,
-                    None => return, // User cancelled
-                };
-
-                self.initiate_download(address.clone(), save_path);
+                self.save_single_download(address.clone(), extension.clone(), from_input, None);
+                return;
             }
             _ => {
                 // Multiple addresses: use directory picker
@@ -255,184 +390,825 @@ impl AntDownloadApp {
                 };
 
                 // Start downloads for each address
-                for (address, extension) in addresses_with_extensions {
-                    let filename = if let Some(ext) = extension {
-                        format!(
-                            "download_{}{}",
-                            address.chars().take(12).collect::<String>(),
-                            ext
-                        )
-                    } else {
-                        format!(
-                            "download_{}.ant",
-                            address.chars().take(12).collect::<String>()
-                        )
-                    };
-                    let save_path = save_dir.join(filename);
-                    self.initiate_download(address, save_path);
+                let downloads = addresses_with_extensions
+                    .into_iter()
+                    .map(|(address, extension)| {
+                        let filename = default_filename(&address, extension.as_deref());
+                        (address, save_dir.join(filename))
+                    })
+                    .collect();
+                self.initiate_downloads(downloads, None, None);
+            }
+        }
+
+        // Clear input for next download
+        if from_input {
+            self.address_input.clear();
+        }
+    }
+
+    fn inspect_address(&mut self, address: String, extension: Option<String>, from_input: bool) {
+        self.is_connecting = true;
+
+        let env = self.selected_env.clone();
+        let connections = self.manager.connections().clone();
+        let tx = self.inspect_sender.clone();
+        tokio::spawn(async move {
+            let mut inspection = AddressInspection {
+                address,
+                extension,
+                entries: None,
+                versions: None,
+                resolved_pointer: None,
+                from_input,
+            };
+            let address = &inspection.address;
+
+            // The pointer is followed once here, the checks below read its target
+            let target = match connections.get(&env).await {
+                Ok(server) => match server.resolve_pointer(address).await {
+                    Ok(resolved) => {
+                        let target = resolved
+                            .as_ref()
+                            .map_or_else(|| address.clone(), |p| p.target.clone());
+                        inspection.resolved_pointer = resolved;
+                        Ok((server, target))
+                    }
+                    Err(error) => Err(error),
+                },
+                Err(error) => Err(error),
+            };
+
+            match target {
+                Ok((server, target))
+                    if AddressKind::detect(&target) == Some(AddressKind::GraphEntry) =>
+                {
+                    inspection.versions = Some(server.graph_history(&target).await);
                 }
+                Ok((server, target)) => {
+                    // If the check fails, the download itself will report the problem
+                    inspection.entries =
+                        server
+                            .archive_entries(&target)
+                            .await
+                            .unwrap_or_else(|error| {
+                                println!("Could not check if {address} is an archive: {error}");
+                                None
+                            });
+                }
+                // Graph entries can't be downloaded directly, show what went wrong
+                Err(error) if AddressKind::detect(address) == Some(AddressKind::GraphEntry) => {
+                    inspection.versions = Some(Err(error));
+                }
+                Err(error) => println!("Could not check if {address} is an archive: {error}"),
             }
+
+            let _ = tx.send(inspection);
+        });
+    }
+
+    fn save_single_download(
+        &mut self,
+        address: String,
+        extension: Option<String>,
+        from_input: bool,
+        resolved_pointer: Option<ResolvedPointer>,
+    ) {
+        // Single address: use file picker
+        let mut dialog = rfd::FileDialog::new().set_title("Save Downloaded File As");
+
+        // Pre-fill filename with extension if provided
+        if let Some(ext) = &extension {
+            let filename = default_filename(&address, Some(ext));
+            dialog = dialog.set_file_name(&filename);
         }
 
+        let save_path = match dialog.save_file() {
+            Some(path) => path,
+            None => return, // User cancelled
+        };
+
+        // The dialog already asked before replacing an existing file
+        self.initiate_downloads(
+            vec![(address, save_path)],
+            Some(ConflictPolicy::Overwrite),
+            resolved_pointer,
+        );
+
         // Clear input for next download
-        self.address_input.clear();
+        if from_input {
+            self.address_input.clear();
+        }
     }
 
-    fn initiate_download(&mut self, address: String, save_path: std::path::PathBuf) {
-        // Generate unique download ID
-        let download_id = format!(
-            "{}_{}",
-            address.chars().take(8).collect::<String>(),
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-                % 10000
+    fn download_archive_entries(&mut self, picker: ArchivePicker) {
+        self.archive_picker = self.download_picked(
+            picker,
+            "Select Directory to Save Archive",
+            |_, entry, save_dir| {
+                let Some(relative_path) = Self::archive_relative_path(&entry.path) else {
+                    println!("Skipping archive entry with unsafe path: {:?}", entry.path);
+                    return None;
+                };
+                Some((entry.address, save_dir.join(relative_path)))
+            },
         );
+    }
 
-        // Create download item
-        let download_item = DownloadItem {
-            address: address.clone(),
-            status: DownloadStatus {
-                state: DownloadState::Waiting,
-                total_bytes_received: 0,
-                chunks_received: 0,
+    fn download_versions(&mut self, picker: VersionPicker) {
+        self.version_picker = self.download_picked(
+            picker,
+            "Select Directory to Save Versions",
+            |index, version, save_dir| {
+                let filename = version_filename(index + 1, &version.content);
+                Some((version.content, save_dir.join(filename)))
             },
-            save_path: Some(save_path.clone()),
-            file_size: 0,
-            created_at: std::time::SystemTime::now(),
+        );
+    }
+
+    // Download the selected items into a folder, `download` gives the address and path of an
+    // item from its position in the list. The picker comes back when the user cancelled the
+    // folder dialog, to keep the selection around.
+    fn download_picked<T>(
+        &mut self,
+        picker: Picker<T>,
+        dialog_title: &str,
+        download: impl Fn(usize, T, &Path) -> Option<(String, PathBuf)>,
+    ) -> Option<Picker<T>> {
+        let Some(save_dir) = rfd::FileDialog::new().set_title(dialog_title).pick_folder() else {
+            return Some(picker);
         };
 
-        self.downloads.insert(download_id.clone(), download_item);
-        self.is_connecting = true;
+        let downloads = picker
+            .items
+            .into_iter()
+            .zip(picker.selected)
+            .enumerate()
+            .filter(|(_, (_, selected))| *selected)
+            .filter_map(|(index, (item, _))| download(index, item, &save_dir))
+            .collect();
+        self.initiate_downloads(downloads, None, None);
 
-        // Create pause channel
-        let (pause_tx, pause_rx) = mpsc::unbounded_channel();
-        self.pause_senders.insert(download_id.clone(), pause_tx);
+        // Clear input for next download
+        if picker.from_input {
+            self.address_input.clear();
+        }
+        None
+    }
 
-        // Start download task
-        let env = self.selected_env.clone();
-        let tx = self.download_sender.clone();
+    // Archive paths come from the uploader, never let them escape the chosen folder
+    fn archive_relative_path(path: &Path) -> Option<PathBuf> {
+        let relative_path = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(Ok(part)),
+                Component::RootDir | Component::Prefix(_) | Component::CurDir => None,
+                Component::ParentDir => Some(Err(())),
+            })
+            .collect::<Result<PathBuf, ()>>()
+            .ok()?;
+
+        (!relative_path.as_os_str().is_empty()).then_some(relative_path)
+    }
 
-        tokio::spawn(async move {
-            let mut pause_rx = pause_rx;
-            let mut is_paused = false;
-
-            // Initialize server
-            match Server::new(&env).await {
-                Ok(server) => {
-                    let _ = tx.send(DownloadEvent::Started {
-                        id: download_id.clone(),
+    fn initiate_downloads(
+        &mut self,
+        downloads: Vec<(String, PathBuf)>,
+        conflict_policy: Option<ConflictPolicy>,
+        // The pointer a single download was checked through
+        resolved_pointer: Option<ResolvedPointer>,
+    ) {
+        // Data that is already downloading, downloaded, or listed twice
+        let mut seen = HashSet::new();
+        let duplicates: Vec<bool> = downloads
+            .iter()
+            .map(|(address, _)| {
+                !seen.insert(address.clone())
+                    || self
+                        .manager
+                        .find_duplicate(address, &self.selected_env)
+                        .is_some()
+            })
+            .collect();
+
+        if duplicates.contains(&true) {
+            self.duplicate_prompt = Some(DuplicatePrompt {
+                downloads,
+                duplicates,
+                environment: self.selected_env.clone(),
+                conflict_policy,
+                resolved_pointer,
+            });
+            return;
+        }
+        let environment = self.selected_env.clone();
+        self.enqueue_downloads(downloads, &environment, conflict_policy, resolved_pointer);
+    }
+
+    fn enqueue_downloads(
+        &mut self,
+        downloads: Vec<(String, PathBuf)>,
+        environment: &str,
+        conflict_policy: Option<ConflictPolicy>,
+        resolved_pointer: Option<ResolvedPointer>,
+    ) {
+        for (address, save_path) in downloads {
+            self.manager.enqueue_resolved(
+                address,
+                environment.to_string(),
+                save_path,
+                conflict_policy,
+                resolved_pointer.clone(),
+            );
+        }
+        self.save_history();
+    }
+
+    // Add a file that is already on disk, and check it matches the address
+    fn verify_existing_file(&mut self) {
+        let addresses = parse_addresses(&self.address_input);
+        let [(address, _)] = addresses.as_slice() else {
+            return;
+        };
+        let address = address.clone();
+
+        let file_path = match rfd::FileDialog::new()
+            .set_title("Select File to Verify")
+            .pick_file()
+        {
+            Some(path) => path,
+            None => return, // User cancelled
+        };
+
+        self.manager
+            .add_existing(address, self.selected_env.clone(), file_path);
+        self.save_history();
+
+        // Clear input for next download
+        self.address_input.clear();
+    }
+
+    // A window listing the items of a picker with checkboxes, `row` shows one item next to its
+    // checkbox
+    fn show_picker<T>(
+        ctx: &egui::Context,
+        title: &str,
+        picker: &mut Picker<T>,
+        summary: String,
+        items_name: &str,
+        mut row: impl FnMut(&mut egui::Ui, usize, &T, &mut bool),
+    ) -> PickerAction {
+        let mut open = true;
+        let mut download_clicked = false;
+        egui::Window::new(title)
+            .open(&mut open)
+            .default_size([600.0, 400.0])
+            .show(ctx, |ui| {
+                if let Some(error) = &picker.error {
+                    ui.label(egui::RichText::new(error).color(egui::Color32::LIGHT_RED));
+                    return;
+                }
+
+                ui.label(
+                    egui::RichText::new(summary)
+                        .size(11.0)
+                        .color(egui::Color32::GRAY),
+                );
+
+                ui.horizontal(|ui| {
+                    if ui.small_button("Select all").clicked() {
+                        picker.selected.fill(true);
+                    }
+                    if ui.small_button("Select none").clicked() {
+                        picker.selected.fill(false);
+                    }
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for (index, (item, selected)) in
+                            picker.items.iter().zip(&mut picker.selected).enumerate()
+                        {
+                            ui.horizontal(|ui| row(ui, index, item, selected));
+                        }
                     });
+                ui.separator();
 
-                    // Create/open save file directly
-                    match std::fs::File::create(&save_path) {
-                        Ok(mut file) => {
-                            // Start downloading
-                            match server.stream_data(&address).await {
-                                Ok(stream) => {
-                                    for chunk_result in stream {
-                                        // Check for pause/resume commands
-                                        if let Ok(should_pause) = pause_rx.try_recv() {
-                                            if should_pause && !is_paused {
-                                                is_paused = true;
-                                                let _ = tx.send(DownloadEvent::Paused {
-                                                    id: download_id.clone(),
-                                                });
-                                            } else if !should_pause && is_paused {
-                                                is_paused = false;
-                                                let _ = tx.send(DownloadEvent::Resumed {
-                                                    id: download_id.clone(),
-                                                });
-                                            }
-                                        }
-
-                                        // If paused, wait until resumed
-                                        while is_paused {
-                                            if let Ok(should_pause) = pause_rx.try_recv() {
-                                                if !should_pause {
-                                                    is_paused = false;
-                                                    let _ = tx.send(DownloadEvent::Resumed {
-                                                        id: download_id.clone(),
-                                                    });
-                                                }
-                                            }
-                                            tokio::time::sleep(tokio::time::Duration::from_millis(
-                                                100,
-                                            ))
-                                            .await;
-                                        }
-
-                                        match chunk_result {
-                                            Ok(chunk) => {
-                                                // Write chunk directly to save file
-                                                if let Err(e) = file.write_all(&chunk) {
-                                                    let _ = tx.send(DownloadEvent::Error {
-                                                        id: download_id.clone(),
-                                                        error: format!("Failed to write file: {e}"),
-                                                    });
-                                                    return;
-                                                }
-
-                                                if tx
-                                                    .send(DownloadEvent::ChunkReceived {
-                                                        id: download_id.clone(),
-                                                        size: chunk.len(),
-                                                    })
-                                                    .is_err()
-                                                {
-                                                    break;
-                                                }
-                                            }
-                                            Err(error) => {
-                                                let _ = tx.send(DownloadEvent::Error {
-                                                    id: download_id.clone(),
-                                                    error,
-                                                });
-                                                return;
-                                            }
-                                        }
-                                    }
+                let selected_count = picker.selected.iter().filter(|s| **s).count();
+                ui.add_enabled_ui(selected_count > 0, |ui| {
+                    if ui
+                        .button(format!("Download {selected_count} {items_name}..."))
+                        .clicked()
+                    {
+                        download_clicked = true;
+                    }
+                });
+            });
 
-                                    // Flush and complete
-                                    if let Err(e) = file.flush() {
-                                        let _ = tx.send(DownloadEvent::Error {
-                                            id: download_id.clone(),
-                                            error: format!("Failed to flush file: {e}"),
-                                        });
-                                        return;
-                                    }
+        if download_clicked {
+            PickerAction::Download
+        } else if !open {
+            PickerAction::Close
+        } else {
+            PickerAction::None
+        }
+    }
 
-                                    let _ = tx.send(DownloadEvent::Completed { id: download_id });
-                                }
-                                Err(error) => {
-                                    let _ = tx.send(DownloadEvent::Error {
-                                        id: download_id,
-                                        error,
-                                    });
-                                }
-                            }
+    fn show_archive_picker(&mut self, ctx: &egui::Context) {
+        let Some(picker) = &mut self.archive_picker else {
+            return;
+        };
+
+        let summary = format!("{} files in archive {}", picker.items.len(), picker.address);
+        let action = Self::show_picker(
+            ctx,
+            "Archive contents",
+            picker,
+            summary,
+            "files",
+            |ui, _, entry, selected| {
+                ui.checkbox(selected, entry.path.display().to_string());
+                let mut details = format_file_size(entry.size as usize);
+                if entry.modified > 0 {
+                    details.push_str(&format!(", modified {}", format_date(entry.modified)));
+                }
+                ui.label(
+                    egui::RichText::new(details)
+                        .size(11.0)
+                        .color(egui::Color32::GRAY),
+                );
+            },
+        );
+
+        match action {
+            PickerAction::Download => {
+                if let Some(picker) = self.archive_picker.take() {
+                    self.download_archive_entries(picker);
+                }
+            }
+            PickerAction::Close => self.archive_picker = None,
+            PickerAction::None => {}
+        }
+    }
+
+    fn show_version_picker(&mut self, ctx: &egui::Context) {
+        let Some(picker) = &mut self.version_picker else {
+            return;
+        };
+
+        let summary = format!(
+            "{} versions from graph entry {}",
+            picker.items.len(),
+            picker.address
+        );
+        let action = Self::show_picker(
+            ctx,
+            "Version history",
+            picker,
+            summary,
+            "versions",
+            |ui, index, version, selected| {
+                ui.checkbox(selected, format!("Version {}", index + 1));
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{}…",
+                        version.content.chars().take(16).collect::<String>()
+                    ))
+                    .size(11.0),
+                )
+                .on_hover_text(format!(
+                    "Content: {}\nGraph entry: {}",
+                    version.content, version.address
+                ));
+
+                // Several descendants mean the history branches here
+                let descendants = match version.descendants.len() {
+                    0 => "latest".to_string(),
+                    1 => "1 descendant".to_string(),
+                    count => format!("{count} descendants"),
+                };
+                ui.label(
+                    egui::RichText::new(descendants)
+                        .size(11.0)
+                        .color(egui::Color32::GRAY),
+                )
+                .on_hover_text(version.descendants.join("\n"));
+            },
+        );
+
+        match action {
+            PickerAction::Download => {
+                if let Some(picker) = self.version_picker.take() {
+                    self.download_versions(picker);
+                }
+            }
+            PickerAction::Close => self.version_picker = None,
+            PickerAction::None => {}
+        }
+    }
+
+    fn show_duplicate_prompt(&mut self, ctx: &egui::Context) {
+        let Some(prompt) = &self.duplicate_prompt else {
+            return;
+        };
+
+        let mut open = true;
+        let mut choice = None;
+        let duplicate_count = prompt.duplicates.iter().filter(|d| **d).count();
+        egui::Window::new("Already downloaded")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{duplicate_count} of {} addresses are already in your downloads or listed twice:",
+                    prompt.downloads.len()
+                ));
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for ((address, _), _) in prompt
+                            .downloads
+                            .iter()
+                            .zip(&prompt.duplicates)
+                            .filter(|(_, duplicate)| **duplicate)
+                        {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "{}…",
+                                    address.chars().take(16).collect::<String>()
+                                ))
+                                .size(11.0)
+                                .color(egui::Color32::GRAY),
+                            );
                         }
-                        Err(e) => {
-                            let _ = tx.send(DownloadEvent::Error {
-                                id: download_id,
-                                error: format!("Failed to create save file: {e}"),
+                    });
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Download again").clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Skip them").clicked() {
+                        choice = Some(false);
+                    }
+                });
+            });
+
+        if let Some(download_again) = choice {
+            if let Some(prompt) = self.duplicate_prompt.take() {
+                let downloads = prompt
+                    .downloads
+                    .into_iter()
+                    .zip(prompt.duplicates)
+                    .filter(|(_, duplicate)| download_again || !duplicate)
+                    .map(|(download, _)| download)
+                    .collect();
+                self.enqueue_downloads(
+                    downloads,
+                    &prompt.environment,
+                    prompt.conflict_policy,
+                    prompt.resolved_pointer,
+                );
+            }
+        } else if !open {
+            self.duplicate_prompt = None;
+        }
+    }
+
+    fn show_connection_status(&self, ui: &mut egui::Ui, status: &ConnectionStatus) {
+        match status {
+            ConnectionStatus::Disconnected => {
+                ui.label(
+                    egui::RichText::new("○ Not connected")
+                        .size(10.0)
+                        .color(egui::Color32::GRAY),
+                );
+            }
+            ConnectionStatus::Connecting => {
+                ui.add(egui::Spinner::new().size(12.0));
+                ui.label(
+                    egui::RichText::new("Connecting...")
+                        .size(10.0)
+                        .color(egui::Color32::YELLOW),
+                );
+            }
+            ConnectionStatus::Connected => {
+                ui.label(
+                    egui::RichText::new("● Connected")
+                        .size(10.0)
+                        .color(egui::Color32::LIGHT_GREEN),
+                );
+            }
+            ConnectionStatus::Failed(error) => {
+                ui.label(
+                    egui::RichText::new("● Connection failed")
+                        .size(10.0)
+                        .color(egui::Color32::LIGHT_RED),
+                )
+                .on_hover_text(error);
+                if ui.small_button("⟳ Reconnect").clicked() {
+                    self.reconnect();
+                }
+            }
+        }
+    }
+
+    fn show_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label(egui::RichText::new("Queue").strong());
+        egui::Grid::new("queue_settings").show(ui, |ui| {
+            ui.label("Parallel downloads");
+            ui.add(egui::DragValue::new(&mut self.manager.queue.max_parallel).clamp_range(1..=32));
+            ui.end_row();
+
+            ui.label("Chunks per download")
+                .on_hover_text("Chunks of one file fetched at the same time");
+            ui.add(egui::DragValue::new(&mut self.manager.parallel_chunks).clamp_range(1..=32));
+            ui.end_row();
+
+            ui.label("If the file exists");
+            egui::ComboBox::from_id_source("conflict_policy")
+                .selected_text(Self::conflict_policy_label(self.manager.conflict_policy))
+                .show_ui(ui, |ui| {
+                    for policy in [
+                        ConflictPolicy::Rename,
+                        ConflictPolicy::Overwrite,
+                        ConflictPolicy::Skip,
+                    ] {
+                        ui.selectable_value(
+                            &mut self.manager.conflict_policy,
+                            policy,
+                            Self::conflict_policy_label(policy),
+                        );
+                    }
+                });
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label(egui::RichText::new("Automatic retries").strong());
+        egui::Grid::new("retry_settings").show(ui, |ui| {
+            ui.label("Max attempts");
+            ui.add(
+                egui::DragValue::new(&mut self.manager.retry_policy.max_attempts)
+                    .clamp_range(1..=50),
+            );
+            ui.end_row();
+
+            ui.label("First delay (s)");
+            ui.add(
+                egui::DragValue::new(&mut self.manager.retry_policy.initial_delay_secs)
+                    .clamp_range(0..=600),
+            );
+            ui.end_row();
+
+            ui.label("Max delay (s)");
+            ui.add(
+                egui::DragValue::new(&mut self.manager.retry_policy.max_delay_secs)
+                    .clamp_range(0..=3600),
+            );
+            ui.end_row();
+
+            ui.label("Jitter");
+            ui.add(
+                egui::Slider::new(&mut self.manager.retry_policy.jitter, 0.0..=1.0)
+                    .fixed_decimals(2),
+            );
+            ui.end_row();
+        });
+
+        ui.separator();
+        if ui.button("Networks...").clicked() {
+            self.network_editor = Some(NetworkEditor {
+                networks: self
+                    .config
+                    .environments
+                    .iter()
+                    .cloned()
+                    .map(NetworkDraft::new)
+                    .collect(),
+                selected: (!self.config.environments.is_empty()).then_some(0),
+                error: None,
+            });
+            ui.close_menu();
+        }
+    }
+
+    fn show_network_editor(&mut self, ctx: &egui::Context) {
+        let Some(editor) = &mut self.network_editor else {
+            return;
+        };
+
+        let mut open = true;
+        let mut save_clicked = false;
+        egui::Window::new("Networks")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new("local, autonomi and alpha are built in")
+                        .size(11.0)
+                        .color(egui::Color32::GRAY),
+                );
+                ui.horizontal(|ui| {
+                    for (index, network) in editor.networks.iter().enumerate() {
+                        let name = match network.environment.name.as_str() {
+                            "" => "(unnamed)",
+                            name => name,
+                        };
+                        if ui
+                            .selectable_label(editor.selected == Some(index), name)
+                            .clicked()
+                        {
+                            editor.selected = Some(index);
+                        }
+                    }
+                    if ui.small_button("➕ Add").clicked() {
+                        editor
+                            .networks
+                            .push(NetworkDraft::new(EnvironmentConfig::default()));
+                        editor.selected = Some(editor.networks.len() - 1);
+                    }
+                });
+                ui.separator();
+
+                if let Some(index) = editor.selected {
+                    let network = &mut editor.networks[index];
+                    egui::Grid::new("network_settings").show(ui, |ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut network.environment.name);
+                        ui.end_row();
+
+                        ui.label("Bootstrap peers")
+                            .on_hover_text("One multiaddr per line");
+                        ui.add(
+                            egui::TextEdit::multiline(&mut network.peers)
+                                .desired_rows(3)
+                                .hint_text("/ip4/1.2.3.4/udp/12000/quic-v1/p2p/..."),
+                        );
+                        ui.end_row();
+
+                        ui.label("Network ID");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut network.network_id)
+                                .hint_text("default"),
+                        );
+                        ui.end_row();
+
+                        ui.label("Local network")
+                            .on_hover_text("Discover peers on the local network");
+                        ui.checkbox(&mut network.environment.local, "");
+                        ui.end_row();
+
+                        ui.label("Ignore bootstrap cache");
+                        ui.checkbox(&mut network.environment.ignore_cache, "");
+                        ui.end_row();
+
+                        ui.label("Bootstrap cache directory");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut network.bootstrap_cache_dir)
+                                .hint_text("default"),
+                        );
+                        ui.end_row();
+
+                        ui.label("EVM network");
+                        egui::ComboBox::from_id_source("evm_network")
+                            .selected_text(Self::evm_network_label(
+                                &network.environment.evm_network,
+                            ))
+                            .show_ui(ui, |ui| {
+                                for evm_network in [
+                                    EvmNetworkConfig::ArbitrumOne,
+                                    EvmNetworkConfig::ArbitrumSepolia,
+                                    EvmNetworkConfig::Custom {
+                                        rpc_url: String::new(),
+                                        payment_token_address: String::new(),
+                                        data_payments_address: String::new(),
+                                    },
+                                ] {
+                                    let selected =
+                                        std::mem::discriminant(&network.environment.evm_network)
+                                            == std::mem::discriminant(&evm_network);
+                                    let label = Self::evm_network_label(&evm_network);
+                                    if ui.selectable_label(selected, label).clicked() && !selected {
+                                        network.environment.evm_network = evm_network;
+                                    }
+                                }
                             });
+                        ui.end_row();
+
+                        if let EvmNetworkConfig::Custom {
+                            rpc_url,
+                            payment_token_address,
+                            data_payments_address,
+                        } = &mut network.environment.evm_network
+                        {
+                            ui.label("RPC URL");
+                            ui.text_edit_singleline(rpc_url);
+                            ui.end_row();
+
+                            ui.label("Payment token address");
+                            ui.text_edit_singleline(payment_token_address);
+                            ui.end_row();
+
+                            ui.label("Data payments address");
+                            ui.text_edit_singleline(data_payments_address);
+                            ui.end_row();
                         }
+                    });
+
+                    if ui.small_button("🗑 Remove network").clicked() {
+                        editor.networks.remove(index);
+                        editor.selected = index
+                            .checked_sub(1)
+                            .or((!editor.networks.is_empty()).then_some(0));
                     }
+                    ui.separator();
+                } else {
+                    ui.label("No custom networks yet");
                 }
-                Err(error) => {
-                    let _ = tx.send(DownloadEvent::Error {
-                        id: download_id,
-                        error,
-                    });
+
+                if let Some(error) = &editor.error {
+                    ui.label(egui::RichText::new(error).color(egui::Color32::LIGHT_RED));
                 }
+                if ui.button("Save").clicked() {
+                    save_clicked = true;
+                }
+            });
+
+        if save_clicked {
+            let config = editor
+                .networks
+                .iter()
+                .map(NetworkDraft::to_environment)
+                .collect::<Result<Vec<_>, _>>()
+                .map(|environments| Config { environments });
+            match config.and_then(|config| config::save(&config).map(|_| config)) {
+                Ok(config) => {
+                    let previous = std::mem::replace(&mut self.config, config);
+                    self.network_editor = None;
+
+                    // Clients of edited networks were set up with the old settings
+                    for env in &self.config.environments {
+                        let changed = previous.environment(&env.name) != Some(env);
+                        let status = self.manager.connections().status(&env.name);
+                        if changed && status != ConnectionStatus::Disconnected {
+                            let env = env.name.clone();
+                            let connections = self.manager.connections().clone();
+                            tokio::spawn(async move {
+                                let _ = connections.reconnect(&env).await;
+                            });
+                        }
+                    }
+
+                    // A removed network falls back to the default one
+                    if !self.config.environment_names().contains(&self.selected_env) {
+                        self.selected_env = DEFAULT_ENVIRONMENT.to_string();
+                    }
+                }
+                Err(e) => editor.error = Some(e),
             }
+        } else if !open {
+            self.network_editor = None;
+        }
+    }
+
+    fn evm_network_label(evm_network: &EvmNetworkConfig) -> &'static str {
+        match evm_network {
+            EvmNetworkConfig::ArbitrumOne => "Arbitrum One",
+            EvmNetworkConfig::ArbitrumSepolia => "Arbitrum Sepolia",
+            EvmNetworkConfig::Custom { .. } => "Custom",
+        }
+    }
+
+    fn conflict_policy_label(policy: ConflictPolicy) -> &'static str {
+        match policy {
+            ConflictPolicy::Rename => "Save under a new name",
+            ConflictPolicy::Overwrite => "Overwrite it",
+            ConflictPolicy::Skip => "Skip the download",
+        }
+    }
+
+    fn reconnect(&self) {
+        let env = self.selected_env.clone();
+        let connections = self.manager.connections().clone();
+        tokio::spawn(async move {
+            let _ = connections.reconnect(&env).await;
         });
     }
 
+    fn save_history(&mut self) {
+        if let Err(e) = history::save(self.manager.downloads()) {
+            println!("Error saving download history: {e}");
+        }
+        self.history_dirty = false;
+        self.history_saved_at = Instant::now();
+    }
+
     fn show_downloads_list(&mut self, ui: &mut egui::Ui) {
-        if self.downloads.is_empty() {
+        if self.manager.downloads().is_empty() {
             ui.vertical_centered(|ui| {
                 ui.add_space(50.0);
                 ui.label(
@@ -456,21 +1232,71 @@ impl AntDownloadApp {
             return;
         }
 
+        let has_finished = self.manager.downloads().values().any(|d| {
+            matches!(
+                d.status.state,
+                DownloadState::Completed
+                    | DownloadState::Error(_)
+                    | DownloadState::Cancelled
+                    | DownloadState::Skipped
+            )
+        });
+        ui.add_enabled_ui(has_finished, |ui| {
+            if ui.small_button("🧹 Clear completed/failed").clicked() {
+                self.manager.clear_finished();
+                self.save_history();
+            }
+        });
+        ui.add_space(5.0);
+
         // Sort downloads by creation time (newest first)
-        let mut sorted_downloads: Vec<_> = self.downloads.iter().collect();
+        let mut sorted_downloads: Vec<_> = self.manager.downloads().iter().collect();
         sorted_downloads.sort_by(|a, b| b.1.created_at.cmp(&a.1.created_at));
 
+        let mut clicked_action = None;
         egui::ScrollArea::vertical()
             .max_height(ui.available_height() - 20.0)
             .show(ui, |ui| {
                 for (download_id, download) in sorted_downloads {
-                    self.show_download_item(ui, download_id, download);
+                    if let Some(action) = self.show_download_item(ui, download_id, download) {
+                        clicked_action = Some((download_id.clone(), action));
+                    }
                     ui.add_space(5.0);
                 }
             });
+
+        // Apply actions once the list is no longer borrowed
+        if let Some((download_id, action)) = clicked_action {
+            match action {
+                DownloadAction::Restart => self.manager.restart(&download_id),
+                DownloadAction::Cancel { delete_file } => {
+                    self.manager.cancel(&download_id, delete_file)
+                }
+                DownloadAction::Remove { delete_file } => {
+                    self.manager.remove(&download_id, delete_file)
+                }
+                DownloadAction::MoveUp => self.manager.queue.move_up(&download_id),
+                DownloadAction::MoveDown => self.manager.queue.move_down(&download_id),
+                DownloadAction::MoveToFront => self.manager.queue.move_to_front(&download_id),
+                DownloadAction::Verify => self.manager.verify(&download_id),
+                DownloadAction::RenameToDetectedType => {
+                    if let Err(e) = self.manager.rename_to_detected_type(&download_id) {
+                        println!("Error renaming {download_id}: {e}");
+                    }
+                }
+            }
+            self.save_history();
+        }
     }
 
-    fn show_download_item(&self, ui: &mut egui::Ui, download_id: &str, download: &DownloadItem) {
+    fn show_download_item(
+        &self,
+        ui: &mut egui::Ui,
+        download_id: &str,
+        download: &DownloadItem,
+    ) -> Option<DownloadAction> {
+        let mut action = None;
+
         let frame = egui::Frame::default()
             .fill(egui::Color32::from_rgb(40, 40, 45))
             .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(60, 60, 65)))
@@ -496,6 +1322,18 @@ impl AntDownloadApp {
                     DownloadState::Waiting => {
                         ui.label(egui::RichText::new("⏳").size(16.0));
                     }
+                    DownloadState::Retrying(_) => {
+                        ui.label(egui::RichText::new("🔁").size(16.0));
+                    }
+                    DownloadState::Interrupted => {
+                        ui.label(egui::RichText::new("⏹").size(16.0));
+                    }
+                    DownloadState::Cancelled => {
+                        ui.label(egui::RichText::new("🚫").size(16.0));
+                    }
+                    DownloadState::Skipped => {
+                        ui.label(egui::RichText::new("⏭").size(16.0));
+                    }
                 }
 
                 ui.add_space(8.0);
@@ -510,7 +1348,14 @@ impl AntDownloadApp {
                         .and_then(|n| n.to_str())
                         .unwrap_or("unknown");
 
-                    let display_address = download.address.clone();
+                    // Private datamaps are long, only show their beginning
+                    let display_address = match AddressKind::detect(&download.address) {
+                        Some(AddressKind::PrivateDataMap) => format!(
+                            "🔒 {}…",
+                            download.address.chars().take(16).collect::<String>()
+                        ),
+                        _ => download.address.clone(),
+                    };
 
                     let display_text = format!("{filename} - {display_address}");
                     ui.label(
@@ -519,10 +1364,46 @@ impl AntDownloadApp {
                             .size(13.0),
                     );
 
+                    // Pointers show the version they were downloaded from
+                    if let Some(pointer) = &download.resolved_pointer {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "➡ {} (pointer version {})",
+                                pointer.target, pointer.counter
+                            ))
+                            .color(egui::Color32::GRAY)
+                            .size(11.0),
+                        );
+                    }
+
+                    // Scratchpads show which version was saved
+                    if let Some(scratchpad) = &download.scratchpad {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "📝 Scratchpad version {}, data encoding {}, {}",
+                                scratchpad.counter,
+                                scratchpad.data_encoding,
+                                format_file_size(download.total_size)
+                            ))
+                            .color(egui::Color32::GRAY)
+                            .size(11.0),
+                        );
+                    }
+
                     ui.add_space(2.0);
 
                     // Status and size
                     match &download.status.state {
+                        DownloadState::Error(error) if download.status.attempts > 1 => {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "Error after {} attempts: {error}",
+                                    download.status.attempts
+                                ))
+                                .color(egui::Color32::LIGHT_RED)
+                                .size(11.0),
+                            );
+                        }
                         DownloadState::Error(error) => {
                             ui.label(
                                 egui::RichText::new(format!("Error: {error}"))
@@ -531,38 +1412,102 @@ impl AntDownloadApp {
                             );
                         }
                         DownloadState::Downloading => {
+                            self.show_progress(ui, download_id, download);
+                        }
+                        DownloadState::Paused => {
+                            if let Some(progress) = download.progress() {
+                                ui.add(egui::ProgressBar::new(progress).desired_width(300.0));
+                            }
                             ui.label(
                                 egui::RichText::new(format!(
-                                    "Downloading... {}",
-                                    self.format_file_size(download.file_size)
+                                    "Paused - {}",
+                                    Self::received_text(download)
                                 ))
-                                .color(egui::Color32::YELLOW)
+                                .color(egui::Color32::from_rgb(255, 165, 0))
                                 .size(11.0),
                             );
                         }
-                        DownloadState::Paused => {
+                        DownloadState::Completed => {
+                            ui.horizontal(|ui| {
+                                ui.label(
+                                    egui::RichText::new(format!(
+                                        "Completed - {}",
+                                        format_file_size(download.status.total_bytes_received)
+                                    ))
+                                    .color(egui::Color32::LIGHT_GREEN)
+                                    .size(11.0),
+                                );
+                                self.show_verification_badge(ui, &download.verification);
+                            });
+
+                            // Offer the extension matching the content
+                            if let Some(file_type) = download.suggested_type() {
+                                ui.horizontal(|ui| {
+                                    ui.label(
+                                        egui::RichText::new(format!(
+                                            "Looks like a {}",
+                                            file_type.name
+                                        ))
+                                        .color(egui::Color32::GRAY)
+                                        .size(11.0),
+                                    );
+                                    let is_verifying =
+                                        download.verification == Some(Verification::Verifying);
+                                    if !is_verifying
+                                        && ui
+                                            .small_button(format!(
+                                                "Rename to .{}",
+                                                file_type.extension
+                                            ))
+                                            .clicked()
+                                    {
+                                        action = Some(DownloadAction::RenameToDetectedType);
+                                    }
+                                });
+                            }
+                        }
+                        DownloadState::Waiting => {
+                            let text = match self.manager.queue.position(download_id) {
+                                Some(position) => format!("Queued (#{position})"),
+                                None => "Waiting...".to_string(),
+                            };
+                            ui.label(
+                                egui::RichText::new(text)
+                                    .color(egui::Color32::GRAY)
+                                    .size(11.0),
+                            );
+                        }
+                        DownloadState::Retrying(error) => {
                             ui.label(
                                 egui::RichText::new(format!(
-                                    "Paused - {}",
-                                    self.format_file_size(download.file_size)
+                                    "Retrying (attempt {} of {}) - {error}",
+                                    download.status.attempts,
+                                    self.manager.retry_policy.max_attempts
                                 ))
                                 .color(egui::Color32::from_rgb(255, 165, 0))
                                 .size(11.0),
                             );
                         }
-                        DownloadState::Completed => {
+                        DownloadState::Interrupted => {
                             ui.label(
                                 egui::RichText::new(format!(
-                                    "Completed - {}",
-                                    self.format_file_size(download.file_size)
+                                    "Interrupted - {}",
+                                    Self::received_text(download)
                                 ))
-                                .color(egui::Color32::LIGHT_GREEN)
+                                .color(egui::Color32::from_rgb(255, 165, 0))
                                 .size(11.0),
                             );
                         }
-                        DownloadState::Waiting => {
+                        DownloadState::Cancelled => {
                             ui.label(
-                                egui::RichText::new("Waiting...")
+                                egui::RichText::new("Cancelled")
+                                    .color(egui::Color32::GRAY)
+                                    .size(11.0),
+                            );
+                        }
+                        DownloadState::Skipped => {
+                            ui.label(
+                                egui::RichText::new("Skipped - the file already exists")
                                     .color(egui::Color32::GRAY)
                                     .size(11.0),
                             );
@@ -572,23 +1517,77 @@ impl AntDownloadApp {
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     // Action buttons
-                    let _file_ready = matches!(download.status.state, DownloadState::Completed)
+                    let file_ready = matches!(download.status.state, DownloadState::Completed)
                         && download
                             .save_path
                             .as_ref()
                             .map(|p| p.exists())
                             .unwrap_or(false);
 
+                    // Cancel running downloads, remove finished ones from the list
+                    let is_running = self.manager.is_pending(download_id);
+                    let is_completed = matches!(download.status.state, DownloadState::Completed);
+                    ui.menu_button("✖", |ui| {
+                        if is_running {
+                            if ui.button("Cancel").clicked() {
+                                action = Some(DownloadAction::Cancel { delete_file: false });
+                                ui.close_menu();
+                            }
+                            if ui.button("Cancel and delete file").clicked() {
+                                action = Some(DownloadAction::Cancel { delete_file: true });
+                                ui.close_menu();
+                            }
+                        } else {
+                            if ui.button("Remove from list").clicked() {
+                                action = Some(DownloadAction::Remove { delete_file: false });
+                                ui.close_menu();
+                            }
+                            if !is_completed && ui.button("Remove and delete file").clicked() {
+                                action = Some(DownloadAction::Remove { delete_file: true });
+                                ui.close_menu();
+                            }
+                        }
+                    });
+
+                    // Reorder buttons (only for queued downloads)
+                    if self.manager.queue.contains(download_id) {
+                        if ui.small_button("⬇").on_hover_text("Move down").clicked() {
+                            action = Some(DownloadAction::MoveDown);
+                        }
+                        if ui.small_button("⬆").on_hover_text("Move up").clicked() {
+                            action = Some(DownloadAction::MoveUp);
+                        }
+                        if ui.small_button("⏫").on_hover_text("Start next").clicked() {
+                            action = Some(DownloadAction::MoveToFront);
+                        }
+                    }
+
+                    // Verify button (only for files on disk)
+                    let is_verifying = download.verification == Some(Verification::Verifying);
+                    if file_ready && !is_verifying && ui.small_button("🔍 Verify").clicked() {
+                        action = Some(DownloadAction::Verify);
+                    }
+
                     // Pause/Resume button (only for active downloads)
                     match &download.status.state {
                         DownloadState::Downloading => {
                             if ui.small_button("⏸ Pause").clicked() {
-                                self.pause_download(download_id);
+                                self.manager.pause(download_id);
                             }
                         }
                         DownloadState::Paused => {
                             if ui.small_button("▶ Resume").clicked() {
-                                self.resume_download(download_id);
+                                self.manager.resume(download_id);
+                            }
+                        }
+                        DownloadState::Interrupted => {
+                            if ui.small_button("▶ Resume").clicked() {
+                                action = Some(DownloadAction::Restart);
+                            }
+                        }
+                        DownloadState::Error(_) => {
+                            if ui.small_button("🔁 Retry").clicked() {
+                                action = Some(DownloadAction::Restart);
                             }
                         }
                         _ => {}
@@ -596,34 +1595,90 @@ impl AntDownloadApp {
                 });
             });
         });
-    }
 
-    fn pause_download(&self, download_id: &str) {
-        if let Some(pause_sender) = self.pause_senders.get(download_id) {
-            let _ = pause_sender.send(true);
-        }
+        action
     }
 
-    fn resume_download(&self, download_id: &str) {
-        if let Some(pause_sender) = self.pause_senders.get(download_id) {
-            let _ = pause_sender.send(false);
+    fn show_progress(&self, ui: &mut egui::Ui, download_id: &str, download: &DownloadItem) {
+        let Some(progress) = download.progress() else {
+            // Total size not known yet
+            ui.label(
+                egui::RichText::new(format!(
+                    "Downloading... {}",
+                    format_file_size(download.status.total_bytes_received)
+                ))
+                .color(egui::Color32::YELLOW)
+                .size(11.0),
+            );
+            return;
+        };
+
+        ui.add(
+            egui::ProgressBar::new(progress)
+                .desired_width(300.0)
+                .text(format!("{:.0}%", progress * 100.0)),
+        );
+
+        let mut details = Self::received_text(download);
+        if let Some(rate) = self.manager.bytes_per_sec(download_id) {
+            details.push_str(&format!(" - {}/s", format_file_size(rate as usize)));
+        }
+        if let Some(eta) = self.manager.eta(download_id) {
+            details.push_str(&format!(" - {} left", format_duration(eta)));
         }
+        ui.label(
+            egui::RichText::new(details)
+                .color(egui::Color32::YELLOW)
+                .size(11.0),
+        );
     }
 
-    fn format_file_size(&self, bytes: usize) -> String {
-        const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
-        let mut size = bytes as f64;
-        let mut unit_index = 0;
-
-        while size >= 1024.0 && unit_index < UNITS.len() - 1 {
-            size /= 1024.0;
-            unit_index += 1;
+    fn received_text(download: &DownloadItem) -> String {
+        let received = format_file_size(download.status.total_bytes_received);
+        match download.progress() {
+            Some(progress) => format!(
+                "{received} of {} ({:.0}%)",
+                format_file_size(download.total_size),
+                progress * 100.0
+            ),
+            None => received,
         }
+    }
 
-        if unit_index == 0 {
-            format!("{} {}", bytes, UNITS[unit_index])
-        } else {
-            format!("{:.1} {}", size, UNITS[unit_index])
+    fn show_verification_badge(&self, ui: &mut egui::Ui, verification: &Option<Verification>) {
+        match verification {
+            None => {}
+            Some(Verification::Verifying) => {
+                ui.add(egui::Spinner::new().size(10.0));
+                ui.label(
+                    egui::RichText::new("Verifying...")
+                        .color(egui::Color32::GRAY)
+                        .size(11.0),
+                );
+            }
+            Some(Verification::Verified) => {
+                ui.label(
+                    egui::RichText::new("✔ Verified")
+                        .color(egui::Color32::LIGHT_GREEN)
+                        .size(11.0),
+                )
+                .on_hover_text("The file matches its network address");
+            }
+            Some(Verification::Mismatch) => {
+                ui.label(
+                    egui::RichText::new("⚠ Does not match address")
+                        .color(egui::Color32::LIGHT_RED)
+                        .size(11.0),
+                );
+            }
+            Some(Verification::Failed(error)) => {
+                ui.label(
+                    egui::RichText::new("⚠ Not verified")
+                        .color(egui::Color32::from_rgb(255, 165, 0))
+                        .size(11.0),
+                )
+                .on_hover_text(error);
+            }
         }
     }
 }
@@ -645,6 +1700,39 @@ fn load_icon() -> egui::IconData {
 
 #[tokio::main]
 async fn main() -> eframe::Result<()> {
+    // Headless mode for scripts and servers without a display
+    let args: Vec<String> = std::env::args().collect();
+    if cli::is_command(&args) {
+        std::process::exit(cli::run(&args).await);
+    }
+
+    // Links like ant://<address>/<filename>, passed by the system when one is opened
+    let links: Vec<String> = args
+        .iter()
+        .skip(1)
+        .filter(|arg| arg.starts_with(URI_SCHEME))
+        .cloned()
+        .collect();
+
+    // A running app opens the links itself, no second window and client bootstrap
+    #[cfg(unix)]
+    let listener = match ipc::forward_or_listen(&links).await {
+        Ok(ipc::Instance::Forwarded) => {
+            println!("Ant Download is already running, opening the links there");
+            return Ok(());
+        }
+        Ok(ipc::Instance::Primary(listener)) => Some(listener),
+        Err(e) => {
+            println!("Could not check for a running instance: {e}");
+            None
+        }
+    };
+
+    let (link_tx, link_rx) = mpsc::unbounded_channel();
+    for link in links {
+        let _ = link_tx.send(link);
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_title("Ant Download")
@@ -656,6 +1744,42 @@ async fn main() -> eframe::Result<()> {
     eframe::run_native(
         "Ant Download",
         options,
-        Box::new(|_cc| Box::new(AntDownloadApp::default())),
+        Box::new(move |cc| {
+            #[cfg(unix)]
+            if let Some(listener) = listener {
+                ipc::listen(listener, link_tx, cc.egui_ctx.clone());
+            }
+            #[cfg(not(unix))]
+            let _ = (cc, link_tx);
+            Box::new(AntDownloadApp::new(link_rx))
+        }),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_archive_paths_inside_the_folder() {
+        let relative_path = |path: &str| AntDownloadApp::archive_relative_path(Path::new(path));
+
+        assert_eq!(
+            relative_path("photos/2024/beach.jpg"),
+            Some(PathBuf::from("photos/2024/beach.jpg"))
+        );
+        assert_eq!(
+            relative_path("./notes.txt"),
+            Some(PathBuf::from("notes.txt"))
+        );
+        // Absolute paths are taken as relative to the chosen folder
+        assert_eq!(
+            relative_path("/etc/passwd"),
+            Some(PathBuf::from("etc/passwd"))
+        );
+        assert_eq!(relative_path("../outside.txt"), None);
+        assert_eq!(relative_path("docs/../../outside.txt"), None);
+        assert_eq!(relative_path("/"), None);
+        assert_eq!(relative_path(""), None);
+    }
+}
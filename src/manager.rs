@@ -0,0 +1,821 @@
+use crate::address;
+use crate::backend::DownloadBackend;
+use crate::connection::ConnectionManager;
+use crate::download::{self, ConflictPolicy, DownloadJob};
+use crate::progress::TransferRate;
+use crate::queue::DownloadQueue;
+use crate::retry::RetryPolicy;
+use crate::server::{Server, DEFAULT_PARALLEL_CHUNKS};
+use crate::verify;
+use crate::{
+    DownloadEvent, DownloadItem, DownloadState, DownloadStatus, ResolvedPointer, Verification,
+};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+/// Queues downloads, runs them in the background and keeps track of their state.
+///
+/// Download tasks report back through events, which are applied when the owner calls
+/// [`DownloadManager::process_events`] or [`DownloadManager::next_event`]. Must be used
+/// from within a tokio runtime.
+pub struct DownloadManager<B: DownloadBackend = Server> {
+    connections: ConnectionManager<B>,
+    downloads: HashMap<String, DownloadItem>,
+    event_receiver: mpsc::UnboundedReceiver<DownloadEvent>,
+    event_sender: mpsc::UnboundedSender<DownloadEvent>,
+    subscribers: Vec<mpsc::UnboundedSender<DownloadEvent>>,
+    pause_senders: HashMap<String, watch::Sender<bool>>,
+    tasks: HashMap<String, tokio::task::JoinHandle<()>>,
+    rates: HashMap<String, TransferRate>,
+    next_id: u64,
+    pub queue: DownloadQueue,
+    pub retry_policy: RetryPolicy,
+    // Ranges of one file fetched at the same time
+    pub parallel_chunks: usize,
+    pub conflict_policy: ConflictPolicy,
+    // Check files against their address once they are downloaded
+    pub verify_completed: bool,
+}
+
+impl<B: DownloadBackend> Default for DownloadManager<B> {
+    fn default() -> Self {
+        Self::new(ConnectionManager::default())
+    }
+}
+
+impl<B: DownloadBackend> DownloadManager<B> {
+    pub fn new(connections: ConnectionManager<B>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            connections,
+            downloads: HashMap::new(),
+            event_receiver: rx,
+            event_sender: tx,
+            subscribers: Vec::new(),
+            pause_senders: HashMap::new(),
+            tasks: HashMap::new(),
+            rates: HashMap::new(),
+            next_id: 0,
+            queue: DownloadQueue::default(),
+            retry_policy: RetryPolicy::default(),
+            parallel_chunks: DEFAULT_PARALLEL_CHUNKS,
+            conflict_policy: ConflictPolicy::default(),
+            verify_completed: true,
+        }
+    }
+
+    pub fn connections(&self) -> &ConnectionManager<B> {
+        &self.connections
+    }
+
+    pub fn downloads(&self) -> &HashMap<String, DownloadItem> {
+        &self.downloads
+    }
+
+    pub fn get(&self, download_id: &str) -> Option<&DownloadItem> {
+        self.downloads.get(download_id)
+    }
+
+    // Add downloads from an earlier session, e.g. loaded with `history::load`
+    pub fn restore(&mut self, downloads: HashMap<String, DownloadItem>) {
+        self.downloads.extend(downloads);
+    }
+
+    // An earlier download of the same data that is still running or whose file is still there
+    pub fn find_duplicate(&self, address: &str, environment: &str) -> Option<&str> {
+        self.downloads
+            .iter()
+            .filter(|(_, download)| {
+                download.address == address && download.environment == environment
+            })
+            .find(|(_, download)| match download.status.state {
+                DownloadState::Completed => download
+                    .save_path
+                    .as_deref()
+                    .is_some_and(|path| path.exists()),
+                DownloadState::Error(_) | DownloadState::Cancelled | DownloadState::Skipped => {
+                    false
+                }
+                // Running, queued, paused or waiting to be resumed
+                _ => true,
+            })
+            .map(|(download_id, _)| download_id.as_str())
+    }
+
+    // A task is running for this download (it may be paused or waiting to retry)
+    pub fn is_active(&self, download_id: &str) -> bool {
+        self.tasks.contains_key(download_id)
+    }
+
+    // Running or still waiting in the queue
+    pub fn is_pending(&self, download_id: &str) -> bool {
+        self.is_active(download_id) || self.queue.contains(download_id)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.tasks.is_empty() || !self.queue.is_empty()
+    }
+
+    // Recent transfer rate, None when nothing arrived lately
+    pub fn bytes_per_sec(&self, download_id: &str) -> Option<f64> {
+        self.rates.get(download_id)?.bytes_per_sec()
+    }
+
+    pub fn eta(&self, download_id: &str) -> Option<Duration> {
+        let download = self.downloads.get(download_id)?;
+        let remaining = download
+            .total_size
+            .checked_sub(download.status.total_bytes_received)?;
+        self.rates.get(download_id)?.eta(remaining)
+    }
+
+    // Every event applied from now on is also sent to the returned receiver
+    pub fn subscribe(&mut self) -> mpsc::UnboundedReceiver<DownloadEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    pub fn enqueue(&mut self, address: String, environment: String, save_path: PathBuf) -> String {
+        self.enqueue_resolved(address, environment, save_path, None, None)
+    }
+
+    // Like `enqueue`, with its own answer to an existing file instead of `conflict_policy`
+    pub fn enqueue_with_policy(
+        &mut self,
+        address: String,
+        environment: String,
+        save_path: PathBuf,
+        conflict_policy: ConflictPolicy,
+    ) -> String {
+        self.enqueue_resolved(address, environment, save_path, Some(conflict_policy), None)
+    }
+
+    // Like `enqueue_with_policy`, for a pointer the caller already followed, e.g. to check what
+    // it points to. The download saves that target rather than following the pointer again.
+    pub fn enqueue_resolved(
+        &mut self,
+        address: String,
+        environment: String,
+        save_path: PathBuf,
+        conflict_policy: Option<ConflictPolicy>,
+        resolved_pointer: Option<ResolvedPointer>,
+    ) -> String {
+        let policy = conflict_policy.unwrap_or(self.conflict_policy);
+        let exists = save_path.exists();
+        // Two downloads to one path would write the same partial file, so a path another
+        // download still holds gets a new name whatever the policy. The policy only decides
+        // about files already on disk.
+        let save_path = if self.is_target_of_pending(&save_path)
+            || (exists && policy == ConflictPolicy::Rename)
+        {
+            download::unique_path(&save_path, |path| self.is_target_of_pending(path))
+        } else {
+            save_path
+        };
+
+        let download_id = self.add_download_item(address, environment, save_path);
+        if let Some(download) = self.downloads.get_mut(&download_id) {
+            download.conflict_policy = conflict_policy;
+            download.resolved_pointer = resolved_pointer;
+            if exists && policy == ConflictPolicy::Skip {
+                download.status.state = DownloadState::Skipped;
+                return download_id;
+            }
+        }
+
+        self.queue.push(download_id.clone());
+        self.schedule();
+        download_id
+    }
+
+    // Another download is going to write this file
+    fn is_target_of_pending(&self, path: &Path) -> bool {
+        self.downloads.iter().any(|(download_id, download)| {
+            self.is_pending(download_id) && download.save_path.as_deref() == Some(path)
+        })
+    }
+
+    // Add a file that is already on disk, and check it matches the address
+    pub fn add_existing(&mut self, address: String, environment: String, path: PathBuf) -> String {
+        let file_size = std::fs::metadata(&path)
+            .map(|metadata| metadata.len() as usize)
+            .unwrap_or(0);
+
+        let download_id = self.add_download_item(address, environment, path);
+        if let Some(download) = self.downloads.get_mut(&download_id) {
+            download.status.state = DownloadState::Completed;
+            download.status.total_bytes_received = file_size;
+            download.total_size = file_size;
+        }
+        self.verify(&download_id);
+        download_id
+    }
+
+    pub fn restart(&mut self, download_id: &str) {
+        let Some(download) = self.downloads.get_mut(download_id) else {
+            return;
+        };
+
+        // Keep the received bytes so the download continues from where it stopped
+        download.status.state = DownloadState::Waiting;
+
+        self.queue.push(download_id.to_string());
+        self.schedule();
+    }
+
+    pub fn pause(&self, download_id: &str) {
+        if let Some(pause_sender) = self.pause_senders.get(download_id) {
+            pause_sender.send_replace(true);
+        }
+    }
+
+    pub fn resume(&self, download_id: &str) {
+        if let Some(pause_sender) = self.pause_senders.get(download_id) {
+            pause_sender.send_replace(false);
+        }
+    }
+
+    pub fn cancel(&mut self, download_id: &str, delete_file: bool) {
+        let task = self.tasks.remove(download_id);
+        if let Some(task) = &task {
+            task.abort();
+        }
+        self.queue.remove(download_id);
+        self.pause_senders.remove(download_id);
+        self.rates.remove(download_id);
+
+        if let Some(download) = self.downloads.get_mut(download_id) {
+            download.status.state = DownloadState::Cancelled;
+            if delete_file {
+                Self::delete_partial_file(download, task);
+            }
+        }
+        self.schedule();
+    }
+
+    pub fn remove(&mut self, download_id: &str, delete_file: bool) {
+        if self.is_pending(download_id) {
+            self.cancel(download_id, delete_file);
+        } else if let Some(download) = self.downloads.get_mut(download_id) {
+            if delete_file {
+                Self::delete_partial_file(download, None);
+            }
+        }
+        self.downloads.remove(download_id);
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.downloads.retain(|_, download| {
+            !matches!(
+                download.status.state,
+                DownloadState::Completed
+                    | DownloadState::Error(_)
+                    | DownloadState::Cancelled
+                    | DownloadState::Skipped
+            )
+        });
+    }
+
+    // Give a downloaded file the extension of its detected type
+    pub fn rename_to_detected_type(&mut self, download_id: &str) -> Result<(), String> {
+        let download = self
+            .downloads
+            .get_mut(download_id)
+            .ok_or("Unknown download")?;
+        let file_type = download.file_type().ok_or("File type not recognized")?;
+        let save_path = download.save_path.clone().ok_or("No file saved")?;
+
+        let new_path = file_type.rename(&save_path);
+        if new_path.exists() {
+            return Err(format!("{new_path:?} already exists"));
+        }
+        std::fs::rename(&save_path, &new_path)
+            .map_err(|e| format!("Failed to rename file: {e}"))?;
+
+        download.save_path = Some(new_path);
+        Ok(())
+    }
+
+    pub fn verify(&mut self, download_id: &str) {
+        let Some(download) = self.downloads.get_mut(download_id) else {
+            return;
+        };
+        let Some(save_path) = download.save_path.clone() else {
+            return;
+        };
+        download.verification = Some(Verification::Verifying);
+
+        // Self-encryption is CPU heavy, keep it off the async workers
+        let address = match &download.resolved_pointer {
+            Some(pointer) => pointer.target.clone(),
+            None => download.address.clone(),
+        };
+        let id = download_id.to_string();
+        let tx = self.event_sender.clone();
+        tokio::spawn(async move {
+            let result =
+                tokio::task::spawn_blocking(move || verify::verify_file(&save_path, &address))
+                    .await
+                    .unwrap_or_else(|e| Err(format!("Verification task failed: {e}")));
+            let _ = tx.send(DownloadEvent::Verified { id, result });
+        });
+    }
+
+    // Apply the events received so far without waiting, and start queued downloads
+    pub fn process_events(&mut self) -> Vec<DownloadEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.event_receiver.try_recv() {
+            if self.apply(&event) {
+                events.push(event);
+            }
+        }
+        self.schedule();
+        events
+    }
+
+    // Wait for the next event and apply it, for programs without a UI loop
+    pub async fn next_event(&mut self) -> Option<DownloadEvent> {
+        loop {
+            let event = self.event_receiver.recv().await?;
+            if self.apply(&event) {
+                self.schedule();
+                return Some(event);
+            }
+        }
+    }
+
+    // Returns false for events that no longer matter
+    fn apply(&mut self, event: &DownloadEvent) -> bool {
+        // Events still queued by a task that was cancelled
+        let cancelled = self
+            .downloads
+            .get(event.id())
+            .is_none_or(|d| matches!(d.status.state, DownloadState::Cancelled));
+        if cancelled {
+            return false;
+        }
+
+        match event {
+            DownloadEvent::PointerResolved { id, pointer } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.resolved_pointer = Some(pointer.clone());
+                }
+            }
+            DownloadEvent::ScratchpadFetched { id, scratchpad } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.scratchpad = Some(scratchpad.clone());
+                }
+            }
+            DownloadEvent::Started {
+                id,
+                offset,
+                total_size,
+            } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.status.state = DownloadState::Downloading;
+                    // Bytes already on disk from a previous attempt
+                    download.status.total_bytes_received = *offset;
+                    download.total_size = *total_size;
+                }
+                self.rates.insert(id.clone(), TransferRate::default());
+            }
+            DownloadEvent::ChunkReceived { id, size } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.status.chunks_received += 1;
+                    download.status.total_bytes_received += size;
+                }
+                self.rates.entry(id.clone()).or_default().record(*size);
+            }
+            DownloadEvent::FileTypeDetected { id, file_type } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.detected_type = Some(file_type.extension.to_string());
+                }
+            }
+            DownloadEvent::Completed { id, path } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.status.state = DownloadState::Completed;
+                    download.save_path = Some(path.clone());
+                }
+                self.pause_senders.remove(id);
+                self.tasks.remove(id);
+                self.rates.remove(id);
+
+                // Files named without knowing their type get a real extension
+                if self
+                    .downloads
+                    .get(id)
+                    .is_some_and(|d| d.has_placeholder_name())
+                {
+                    if let Err(e) = self.rename_to_detected_type(id) {
+                        eprintln!("Keeping placeholder name for {id}: {e}");
+                    }
+                }
+                // Scratchpads have no content address to check against
+                let is_scratchpad = self
+                    .downloads
+                    .get(id)
+                    .is_some_and(|d| d.scratchpad.is_some());
+                if self.verify_completed && !is_scratchpad {
+                    self.verify(id);
+                }
+            }
+            DownloadEvent::Paused { id } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.status.state = DownloadState::Paused;
+                }
+            }
+            DownloadEvent::Resumed { id } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.status.state = DownloadState::Downloading;
+                }
+            }
+            DownloadEvent::Error { id, error } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.status.state = DownloadState::Error(error.to_string());
+                }
+                self.pause_senders.remove(id);
+                self.tasks.remove(id);
+                self.rates.remove(id);
+            }
+            DownloadEvent::Retrying { id, attempt, error } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.status.state = DownloadState::Retrying(error.clone());
+                    download.status.attempts = *attempt;
+                }
+            }
+            DownloadEvent::Verified { id, result } => {
+                if let Some(download) = self.downloads.get_mut(id) {
+                    download.verification = Some(match result {
+                        Ok(true) => Verification::Verified,
+                        Ok(false) => Verification::Mismatch,
+                        Err(error) => Verification::Failed(error.clone()),
+                    });
+                }
+            }
+        }
+
+        // Drop subscribers that went away
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        true
+    }
+
+    fn add_download_item(
+        &mut self,
+        address: String,
+        environment: String,
+        save_path: PathBuf,
+    ) -> String {
+        // Counter based, skipping IDs restored from history
+        let download_id = loop {
+            self.next_id += 1;
+            let download_id = format!(
+                "{}-{}",
+                address::hex_part(&address)
+                    .chars()
+                    .take(8)
+                    .collect::<String>(),
+                self.next_id
+            );
+            if !self.downloads.contains_key(&download_id) {
+                break download_id;
+            }
+        };
+
+        // Create download item
+        let download_item = DownloadItem {
+            address,
+            environment,
+            status: DownloadStatus {
+                state: DownloadState::Waiting,
+                total_bytes_received: 0,
+                chunks_received: 0,
+                attempts: 0,
+            },
+            save_path: Some(save_path),
+            created_at: std::time::SystemTime::now(),
+            verification: None,
+            detected_type: None,
+            resolved_pointer: None,
+            scratchpad: None,
+            total_size: 0,
+            conflict_policy: None,
+        };
+
+        self.downloads.insert(download_id.clone(), download_item);
+        download_id
+    }
+
+    // Start queued downloads in the slots freed by finished ones
+    fn schedule(&mut self) {
+        while let Some(download_id) = self.queue.next(self.tasks.len()) {
+            self.spawn(download_id);
+        }
+    }
+
+    fn spawn(&mut self, download_id: String) {
+        let Some(download) = self.downloads.get_mut(&download_id) else {
+            return;
+        };
+        let Some(save_path) = download.save_path.clone() else {
+            return;
+        };
+        let address = download.address.clone();
+        let resume_from = download.status.total_bytes_received;
+        // Nothing downloaded yet, so the pointer may lead somewhere newer by now, unless the
+        // caller resolved it before the first run
+        let resolved_pointer = download
+            .resolved_pointer
+            .clone()
+            .filter(|_| resume_from > 0 || download.status.attempts == 0);
+        download.status.attempts = 1;
+        let scratchpad = download.scratchpad.clone().filter(|_| resume_from > 0);
+
+        // Create pause channel
+        let (pause_tx, pause_rx) = watch::channel(false);
+        self.pause_senders.insert(download_id.clone(), pause_tx);
+
+        // Start download task
+        let job = DownloadJob {
+            id: download_id.clone(),
+            address,
+            environment: download.environment.clone(),
+            save_path,
+            resume_from,
+            retry_policy: self.retry_policy.clone(),
+            parallel_chunks: self.parallel_chunks,
+            conflict_policy: download.conflict_policy.unwrap_or(self.conflict_policy),
+            resolved_pointer,
+            scratchpad,
+        };
+        let task = tokio::spawn(download::run_download(
+            job,
+            self.connections.clone(),
+            self.event_sender.clone(),
+            pause_rx,
+        ));
+        self.tasks.insert(download_id, task);
+    }
+
+    // A file operation the aborted task already started still finishes, so the file is only
+    // deleted once the task is done
+    fn delete_partial_file(
+        download: &mut DownloadItem,
+        aborted_task: Option<tokio::task::JoinHandle<()>>,
+    ) {
+        let Some(save_path) = &download.save_path else {
+            return;
+        };
+        let path = download::part_path(save_path);
+        tokio::spawn(async move {
+            if let Some(task) = aborted_task {
+                let _ = task.await;
+            }
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => eprintln!("Error deleting partial file {path:?}: {e}"),
+            }
+        });
+
+        // Nothing left to resume from
+        download.status.total_bytes_received = 0;
+        download.total_size = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{manager, temp_path, MockBackend, CONTENT};
+    use std::time::Duration;
+
+    const ADDRESSES: [&str; 3] = [
+        "a0a1a2a3a4a5a6a7a8a9aaabacadaeafb0b1b2b3b4b5b6b7b8b9babbbcbdbebf",
+        "b0a1a2a3a4a5a6a7a8a9aaabacadaeafb0b1b2b3b4b5b6b7b8b9babbbcbdbebf",
+        "c0a1a2a3a4a5a6a7a8a9aaabacadaeafb0b1b2b3b4b5b6b7b8b9babbbcbdbebf",
+    ];
+
+    #[tokio::test]
+    async fn runs_queued_downloads_and_notifies_subscribers() {
+        let mut mock = MockBackend::new().with_latency(Duration::from_millis(1));
+        for address in ADDRESSES {
+            mock = mock.with_file(address, CONTENT);
+        }
+        let mut manager = manager(&mock);
+        manager.queue.max_parallel = 2;
+        let mut events = manager.subscribe();
+
+        let ids: Vec<String> = ADDRESSES
+            .iter()
+            .enumerate()
+            .map(|(i, address)| {
+                manager.enqueue(
+                    address.to_string(),
+                    "mock".to_string(),
+                    temp_path(&format!("queued_{i}")),
+                )
+            })
+            .collect();
+        assert_eq!(manager.queue.position(&ids[2]), Some(1));
+
+        while manager.has_pending() {
+            manager.next_event().await;
+        }
+
+        for id in &ids {
+            let download = manager.get(id).unwrap();
+            assert!(matches!(download.status.state, DownloadState::Completed));
+            assert_eq!(download.status.total_bytes_received, CONTENT.len());
+            assert_eq!(download.total_size, CONTENT.len());
+            let path = download.save_path.clone().unwrap();
+            assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+            let _ = std::fs::remove_file(path);
+        }
+
+        let mut completed = 0;
+        while let Ok(event) = events.try_recv() {
+            if matches!(event, DownloadEvent::Completed { .. }) {
+                completed += 1;
+            }
+        }
+        assert_eq!(completed, ids.len());
+    }
+
+    #[tokio::test]
+    async fn renames_placeholder_to_detected_type() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let mock = MockBackend::new().with_file(ADDRESSES[0], png);
+        let mut manager = manager(&mock);
+        let path = temp_path("detected.ant");
+
+        let id = manager.enqueue(ADDRESSES[0].to_string(), "mock".to_string(), path.clone());
+        while manager.has_pending() {
+            manager.next_event().await;
+        }
+
+        let download = manager.get(&id).unwrap();
+        assert_eq!(download.detected_type.as_deref(), Some("png"));
+        assert_eq!(download.save_path, Some(path.with_extension("png")));
+        assert!(download.suggested_type().is_none());
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(path.with_extension("png")).unwrap(), png);
+        let _ = std::fs::remove_file(path.with_extension("png"));
+    }
+
+    #[tokio::test]
+    async fn replaces_file_when_asked_despite_rename_policy() {
+        let mock = MockBackend::new().with_file(ADDRESSES[0], CONTENT);
+        let mut manager = manager(&mock);
+        let path = temp_path("replace.txt");
+        std::fs::write(&path, b"old").unwrap();
+
+        let id = manager.enqueue_with_policy(
+            ADDRESSES[0].to_string(),
+            "mock".to_string(),
+            path.clone(),
+            ConflictPolicy::Overwrite,
+        );
+        while manager.has_pending() {
+            manager.next_event().await;
+        }
+
+        let download = manager.get(&id).unwrap();
+        assert_eq!(download.save_path, Some(path.clone()));
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn overwrite_keeps_downloads_to_one_path_apart() {
+        let other_content = b"a different file with the same name";
+        let mock = MockBackend::new()
+            .with_file(ADDRESSES[0], CONTENT)
+            .with_file(ADDRESSES[1], other_content)
+            .with_latency(Duration::from_millis(1));
+        let mut manager = manager(&mock);
+        let path = temp_path("shared.txt");
+
+        let ids: Vec<String> = ADDRESSES[..2]
+            .iter()
+            .map(|address| {
+                manager.enqueue_with_policy(
+                    address.to_string(),
+                    "mock".to_string(),
+                    path.clone(),
+                    ConflictPolicy::Overwrite,
+                )
+            })
+            .collect();
+        while manager.has_pending() {
+            manager.next_event().await;
+        }
+
+        let first = manager.get(&ids[0]).unwrap();
+        let second = manager.get(&ids[1]).unwrap();
+        assert!(matches!(first.status.state, DownloadState::Completed));
+        assert!(matches!(second.status.state, DownloadState::Completed));
+        assert_eq!(first.save_path, Some(path.clone()));
+        let second_path = second.save_path.clone().unwrap();
+        assert_ne!(second_path, path);
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        assert_eq!(std::fs::read(&second_path).unwrap(), other_content);
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(second_path);
+    }
+
+    #[tokio::test]
+    async fn keeps_pointer_target_resolved_by_caller() {
+        let pointer = "pointer:".to_string() + &"c".repeat(96);
+        let mock = MockBackend::new()
+            .with_file(ADDRESSES[0], CONTENT)
+            .with_file(ADDRESSES[1], b"published after the check")
+            .with_pointer(&pointer, ADDRESSES[1], 8);
+        let mut manager = manager(&mock);
+        let path = temp_path("checked_pointer");
+
+        // The caller saw the previous version of the pointer
+        let checked = ResolvedPointer {
+            target: ADDRESSES[0].to_string(),
+            counter: 7,
+        };
+        let id = manager.enqueue_resolved(
+            pointer,
+            "mock".to_string(),
+            path.clone(),
+            None,
+            Some(checked.clone()),
+        );
+        while manager.has_pending() {
+            manager.next_event().await;
+        }
+
+        let download = manager.get(&id).unwrap();
+        assert!(matches!(download.status.state, DownloadState::Completed));
+        assert_eq!(download.resolved_pointer, Some(checked));
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn same_address_gets_distinct_ids() {
+        let mock = MockBackend::new().with_file(ADDRESSES[0], CONTENT);
+        let mut manager = manager(&mock);
+        let first_path = temp_path("twice_1");
+        let second_path = temp_path("twice_2");
+
+        assert_eq!(manager.find_duplicate(ADDRESSES[0], "mock"), None);
+        let first = manager.enqueue(ADDRESSES[0].to_string(), "mock".to_string(), first_path);
+        assert_eq!(
+            manager.find_duplicate(ADDRESSES[0], "mock"),
+            Some(first.as_str())
+        );
+        assert_eq!(manager.find_duplicate(ADDRESSES[0], "other"), None);
+        let second = manager.enqueue(ADDRESSES[0].to_string(), "mock".to_string(), second_path);
+        assert_ne!(first, second);
+
+        while manager.has_pending() {
+            manager.next_event().await;
+        }
+        for id in [&first, &second] {
+            let download = manager.get(id).unwrap();
+            assert!(matches!(download.status.state, DownloadState::Completed));
+            let _ = std::fs::remove_file(download.save_path.clone().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_ignores_late_events() {
+        let mock = MockBackend::new()
+            .with_file(ADDRESSES[0], CONTENT)
+            .with_chunk_size(1)
+            .with_latency(Duration::from_millis(5));
+        let mut manager = manager(&mock);
+        let path = temp_path("cancel");
+
+        let id = manager.enqueue(ADDRESSES[0].to_string(), "mock".to_string(), path.clone());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        manager.cancel(&id, true);
+
+        assert!(manager.process_events().is_empty());
+        assert!(!manager.has_pending());
+        assert!(matches!(
+            manager.get(&id).unwrap().status.state,
+            DownloadState::Cancelled
+        ));
+        assert!(!path.exists());
+
+        // The partial file goes once the aborted task is done
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while download::part_path(&path).exists() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("the partial file should be deleted");
+    }
+}
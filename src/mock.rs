@@ -0,0 +1,298 @@
+use crate::address;
+use crate::backend::{DataInfo, DataStream, DownloadBackend};
+use crate::connection::ConnectionManager;
+use crate::download::{ConflictPolicy, DownloadJob};
+use crate::manager::DownloadManager;
+use crate::retry::RetryPolicy;
+use crate::server::{GraphVersion, ServerError};
+use crate::{ResolvedPointer, ScratchpadInfo};
+
+use autonomi::Bytes;
+use futures::StreamExt;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const ADDRESS: &str = "a0a1a2a3a4a5a6a7a8a9aaabacadaeafb0b1b2b3b4b5b6b7b8b9babbbcbdbebf";
+pub const CONTENT: &[u8] = b"the quick brown fox jumps over the lazy dog";
+
+// Unique to the test run, tests pick distinct names
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ant_download_test_{}_{name}", std::process::id()))
+}
+
+// A download of ADDRESS that retries right away
+pub fn job(save_path: PathBuf) -> DownloadJob {
+    DownloadJob {
+        id: "test".to_string(),
+        address: ADDRESS.to_string(),
+        environment: "mock".to_string(),
+        save_path,
+        resume_from: 0,
+        retry_policy: RetryPolicy {
+            max_attempts: 3,
+            initial_delay_secs: 0,
+            max_delay_secs: 0,
+            jitter: 0.0,
+        },
+        parallel_chunks: 1,
+        conflict_policy: ConflictPolicy::Rename,
+        resolved_pointer: None,
+        scratchpad: None,
+    }
+}
+
+// Every environment connects to the same mock
+pub fn connections(mock: &MockBackend) -> ConnectionManager<MockBackend> {
+    let mock = mock.clone();
+    ConnectionManager::with_connector(move |_environment| {
+        let mock = mock.clone();
+        async move { Ok(mock) }
+    })
+}
+
+pub fn manager(mock: &MockBackend) -> DownloadManager<MockBackend> {
+    let mut manager = DownloadManager::new(connections(mock));
+    // The mock addresses are not real self-encryption results
+    manager.verify_completed = false;
+    manager
+}
+
+// Something going wrong with one stream
+#[derive(Debug, Clone, Copy)]
+pub enum Fault {
+    // The stream can't be opened at all
+    StreamError {
+        transient: bool,
+    },
+    // A chunk fails after `after_chunks` good ones
+    ChunkError {
+        after_chunks: usize,
+        transient: bool,
+    },
+    // The stream ends without error after `after_chunks` good ones
+    Truncate {
+        after_chunks: usize,
+    },
+}
+
+// In-memory network, files are served in chunks of `chunk_size` bytes
+#[derive(Clone)]
+pub struct MockBackend {
+    files: Arc<Mutex<HashMap<String, Bytes>>>,
+    pointers: Arc<Mutex<HashMap<String, ResolvedPointer>>>,
+    scratchpads: Arc<Mutex<HashMap<String, ScratchpadInfo>>>,
+    graph_entries: Arc<Mutex<HashMap<String, GraphVersion>>>,
+    // Consumed in order, one per opened stream
+    faults: Arc<Mutex<VecDeque<Fault>>>,
+    offsets: Arc<Mutex<Vec<usize>>>,
+    chunk_size: usize,
+    latency: Duration,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self {
+            files: Arc::default(),
+            pointers: Arc::default(),
+            scratchpads: Arc::default(),
+            graph_entries: Arc::default(),
+            faults: Arc::default(),
+            offsets: Arc::default(),
+            chunk_size: 4,
+            latency: Duration::ZERO,
+        }
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file(self, address: &str, content: &[u8]) -> Self {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(address.to_string(), Bytes::copy_from_slice(content));
+        self
+    }
+
+    pub fn with_pointer(self, address: &str, target: &str, counter: u64) -> Self {
+        self.pointers.lock().unwrap().insert(
+            address.to_string(),
+            ResolvedPointer {
+                target: target.to_string(),
+                counter,
+            },
+        );
+        self
+    }
+
+    // Point an existing pointer somewhere else, like a publisher pushing a new version
+    pub fn update_pointer(&self, address: &str, target: &str) {
+        if let Some(pointer) = self.pointers.lock().unwrap().get_mut(address) {
+            pointer.target = target.to_string();
+            pointer.counter += 1;
+        }
+    }
+
+    // A scratchpad serves its payload like a file, plus its version details
+    pub fn with_scratchpad(self, address: &str, content: &[u8], counter: u64) -> Self {
+        self.scratchpads.lock().unwrap().insert(
+            address.to_string(),
+            ScratchpadInfo {
+                counter,
+                data_encoding: 0,
+            },
+        );
+        self.with_file(address, content)
+    }
+
+    // Write a new version of a scratchpad
+    pub fn update_scratchpad(&self, address: &str, content: &[u8]) {
+        if let Some(scratchpad) = self.scratchpads.lock().unwrap().get_mut(address) {
+            scratchpad.counter += 1;
+        }
+        self.files
+            .lock()
+            .unwrap()
+            .insert(address.to_string(), Bytes::copy_from_slice(content));
+    }
+
+    // Graph entries are looked up by their hex address, without prefix
+    pub fn with_graph_entry(self, address: &str, content: &str, descendants: &[&str]) -> Self {
+        self.graph_entries.lock().unwrap().insert(
+            address.to_string(),
+            GraphVersion {
+                address: address.to_string(),
+                content: content.to_string(),
+                descendants: descendants.iter().map(|d| d.to_string()).collect(),
+            },
+        );
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_fault(self, fault: Fault) -> Self {
+        self.faults.lock().unwrap().push_back(fault);
+        self
+    }
+
+    // Offset of every stream opened so far
+    pub fn requested_offsets(&self) -> Vec<usize> {
+        self.offsets.lock().unwrap().clone()
+    }
+
+    fn file(&self, address: &str) -> Result<Bytes, ServerError> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(address)
+            .cloned()
+            .ok_or_else(|| ServerError::Data(format!("No mock file at {address}")))
+    }
+}
+
+fn fault_error(transient: bool) -> ServerError {
+    if transient {
+        ServerError::Network("Mock network error".to_string())
+    } else {
+        ServerError::Data("Mock decryption error".to_string())
+    }
+}
+
+impl DownloadBackend for MockBackend {
+    // The file as it was when opened
+    type Data = Bytes;
+
+    async fn connect(_environment: &str) -> Result<Self, ServerError> {
+        Ok(MockBackend::new())
+    }
+
+    async fn resolve(&self, address: &str) -> Result<Option<ResolvedPointer>, ServerError> {
+        Ok(self.pointers.lock().unwrap().get(address).cloned())
+    }
+
+    async fn open(&self, address: &str) -> Result<(DataInfo, Bytes), ServerError> {
+        let data = self.file(address)?;
+        let scratchpad = self.scratchpads.lock().unwrap().get(address).cloned();
+        let info = DataInfo {
+            size: data.len(),
+            content: scratchpad.is_some().then(|| data.clone()),
+            scratchpad,
+        };
+        Ok((info, data))
+    }
+
+    async fn stream(
+        &self,
+        data: &Bytes,
+        offset: usize,
+        parallel_chunks: usize,
+    ) -> Result<DataStream, ServerError> {
+        self.offsets.lock().unwrap().push(offset);
+        let data = data.clone();
+
+        let fault = self.faults.lock().unwrap().pop_front();
+        if let Some(Fault::StreamError { transient }) = fault {
+            return Err(fault_error(transient));
+        }
+
+        let chunk_size = self.chunk_size;
+        let latency = self.latency;
+        let mut position = offset.min(data.len());
+        let mut chunks_sent = 0;
+        let chunks = std::iter::from_fn(move || {
+            if position >= data.len() {
+                return None;
+            }
+            match fault {
+                Some(Fault::ChunkError {
+                    after_chunks,
+                    transient,
+                }) if chunks_sent == after_chunks => {
+                    position = data.len();
+                    return Some(Err(fault_error(transient)));
+                }
+                Some(Fault::Truncate { after_chunks }) if chunks_sent == after_chunks => {
+                    return None;
+                }
+                _ => {}
+            }
+
+            let end = (position + chunk_size).min(data.len());
+            let chunk = data.slice(position..end);
+            position = end;
+            chunks_sent += 1;
+            Some(Ok(chunk))
+        });
+
+        Ok(futures::stream::iter(chunks)
+            .map(move |chunk| async move {
+                tokio::time::sleep(latency).await;
+                chunk
+            })
+            .buffered(parallel_chunks.max(1))
+            .boxed())
+    }
+
+    async fn graph_entry(&self, address: &str) -> Result<GraphVersion, ServerError> {
+        self.graph_entries
+            .lock()
+            .unwrap()
+            .get(address::hex_part(address))
+            .cloned()
+            .ok_or_else(|| ServerError::Network(format!("No mock graph entry at {address}")))
+    }
+}
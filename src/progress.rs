@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Only the last few seconds count, so the rate follows the network as it changes
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+// Rolling transfer rate of one download
+#[derive(Debug, Default)]
+pub struct TransferRate {
+    samples: VecDeque<(Instant, usize)>,
+}
+
+impl TransferRate {
+    pub fn record(&mut self, bytes: usize) {
+        self.record_at(Instant::now(), bytes);
+    }
+
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        self.bytes_per_sec_at(Instant::now())
+    }
+
+    // Time left for `remaining` bytes at the current rate
+    pub fn eta(&self, remaining: usize) -> Option<Duration> {
+        let rate = self.bytes_per_sec().filter(|rate| *rate > 0.0)?;
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    fn record_at(&mut self, now: Instant, bytes: usize) {
+        self.samples.push_back((now, bytes));
+        while let Some((time, _)) = self.samples.front() {
+            if now.duration_since(*time) <= RATE_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    fn bytes_per_sec_at(&self, now: Instant) -> Option<f64> {
+        let recent = self
+            .samples
+            .iter()
+            .filter(|(time, _)| now.duration_since(*time) <= RATE_WINDOW);
+        let (first, bytes) = recent.fold((None, 0), |(first, bytes), (time, size)| {
+            (first.or(Some(*time)), bytes + size)
+        });
+
+        // Nothing arrived lately, e.g. while paused
+        let first = first?;
+
+        // A single burst of chunks is spread over at least a second
+        let elapsed = now.duration_since(first).max(Duration::from_secs(1));
+        Some(bytes as f64 / elapsed.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_follows_recent_chunks() {
+        let start = Instant::now();
+        let mut rate = TransferRate::default();
+        assert_eq!(rate.bytes_per_sec_at(start), None);
+
+        rate.record_at(start, 1000);
+        rate.record_at(start + Duration::from_secs(1), 1000);
+        rate.record_at(start + Duration::from_secs(2), 1000);
+        assert_eq!(
+            rate.bytes_per_sec_at(start + Duration::from_secs(2)),
+            Some(1500.0)
+        );
+
+        // Old samples fall out of the window, and with nothing new the rate is unknown
+        assert_eq!(
+            rate.bytes_per_sec_at(start + Duration::from_secs(7)),
+            Some(200.0)
+        );
+        assert_eq!(rate.bytes_per_sec_at(start + Duration::from_secs(20)), None);
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+const DEFAULT_MAX_PARALLEL: usize = 4;
+
+// Downloads waiting for a free slot, in the order they will start
+pub struct DownloadQueue {
+    waiting: VecDeque<String>,
+    pub max_parallel: usize,
+}
+
+impl Default for DownloadQueue {
+    fn default() -> Self {
+        Self {
+            waiting: VecDeque::new(),
+            max_parallel: DEFAULT_MAX_PARALLEL,
+        }
+    }
+}
+
+impl DownloadQueue {
+    pub fn push(&mut self, download_id: String) {
+        if !self.waiting.contains(&download_id) {
+            self.waiting.push_back(download_id);
+        }
+    }
+
+    pub fn remove(&mut self, download_id: &str) -> bool {
+        let len = self.waiting.len();
+        self.waiting.retain(|id| id != download_id);
+        self.waiting.len() != len
+    }
+
+    pub fn contains(&self, download_id: &str) -> bool {
+        self.waiting.iter().any(|id| id == download_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+
+    // 1-based position, as shown to the user
+    pub fn position(&self, download_id: &str) -> Option<usize> {
+        self.waiting
+            .iter()
+            .position(|id| id == download_id)
+            .map(|index| index + 1)
+    }
+
+    // Next download to start, if fewer than `max_parallel` are running
+    pub fn next(&mut self, running: usize) -> Option<String> {
+        if running >= self.max_parallel {
+            return None;
+        }
+        self.waiting.pop_front()
+    }
+
+    pub fn move_up(&mut self, download_id: &str) {
+        if let Some(index) = self.waiting.iter().position(|id| id == download_id) {
+            if index > 0 {
+                self.waiting.swap(index, index - 1);
+            }
+        }
+    }
+
+    pub fn move_down(&mut self, download_id: &str) {
+        if let Some(index) = self.waiting.iter().position(|id| id == download_id) {
+            if index + 1 < self.waiting.len() {
+                self.waiting.swap(index, index + 1);
+            }
+        }
+    }
+
+    pub fn move_to_front(&mut self, download_id: &str) {
+        if self.remove(download_id) {
+            self.waiting.push_front(download_id.to_string());
+        }
+    }
+}
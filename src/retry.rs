@@ -0,0 +1,43 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay_secs: u64,
+    pub max_delay_secs: u64,
+    // Fraction of the delay randomly added or removed, so downloads don't retry in lockstep
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay_secs: 2,
+            max_delay_secs: 60,
+            jitter: 0.25,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Delay before the next attempt, after `attempt` attempts have failed
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let backoff = self
+            .initial_delay_secs
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay_secs) as f64;
+
+        let jitter = backoff * self.jitter * (random_unit() * 2.0 - 1.0);
+        Duration::from_secs_f64((backoff + jitter).max(0.0))
+    }
+}
+
+// Random number in [0, 1), std's hasher seeds are random so no extra dependency is needed
+fn random_unit() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
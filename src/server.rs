@@ -1,20 +1,154 @@
+use crate::address::{self, AddressKind};
+use crate::config;
+use crate::{ResolvedPointer, ScratchpadInfo};
+
+use autonomi::chunk::ChunkAddress;
+use autonomi::client::GetError;
+use autonomi::data::private::DataMapChunk;
 use autonomi::data::DataAddress;
-use autonomi::{Bytes, Client};
+use autonomi::files::PublicArchive;
+use autonomi::graph::GraphEntryAddress;
+use autonomi::pointer::{PointerAddress, PointerTarget};
+use autonomi::scratchpad::ScratchpadAddress;
+use autonomi::{Bytes, Client, XorName};
+use futures::{Stream, StreamExt};
+use self_encryption::DataMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub const ENVIRONMENTS: [&str; 3] = ["local", "autonomi", "alpha"];
 pub const DEFAULT_ENVIRONMENT: &str = "autonomi";
 
+// Size of each range read from the decrypted data, one self-encryption chunk
+const RANGE_SIZE: usize = 4 * 1024 * 1024;
+
+// Ranges fetched at the same time for one download, unless configured otherwise
+pub const DEFAULT_PARALLEL_CHUNKS: usize = 4;
+
+// Archives are small, bigger data is never fetched just to find out it isn't one
+const MAX_ARCHIVE_SIZE: usize = 16 * 1024 * 1024;
+
+// Pointers followed before giving up on a chain, it most likely loops
+const MAX_POINTER_HOPS: usize = 16;
+
+// Versions listed before giving up on walking a graph
+pub const MAX_GRAPH_VERSIONS: usize = 1000;
+
+// Reads `len` bytes at `start` from data opened with `Server::open_data`
+type RangeReader = Arc<dyn Fn(usize, usize) -> Result<Bytes, ServerError> + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub enum ServerError {
+    Connection(String),
+    // The network settings are wrong, connecting again won't help
+    Config(String),
+    InvalidAddress(String),
+    Network(String),
+    Data(String),
+}
+
+impl ServerError {
+    // Errors worth trying again, the network may behave better next time
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ServerError::Connection(_) | ServerError::Network(_))
+    }
+
+    fn from_get_error(context: &str, error: GetError) -> Self {
+        let message = format!("{context}{error}");
+        match error {
+            GetError::Network(_) => ServerError::Network(message),
+            // A record the network doesn't have won't turn up by asking again
+            _ => ServerError::Data(message),
+        }
+    }
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::Connection(message)
+            | ServerError::Config(message)
+            | ServerError::InvalidAddress(message)
+            | ServerError::Network(message)
+            | ServerError::Data(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub address: String,
+    pub size: u64,
+    // Seconds since the Unix epoch, 0 when unknown
+    pub modified: u64,
+}
+
+// One revision in a chain of graph entries
+#[derive(Debug, Clone)]
+pub struct GraphVersion {
+    pub address: String,
+    // Data address of this revision's content
+    pub content: String,
+    // Graph entries of the revisions that follow
+    pub descendants: Vec<String>,
+}
+
+// Outcome of fetching a single chunk, to find out which part of some data is missing
+#[derive(Debug, Clone)]
+pub struct ChunkReport {
+    // Encrypted content as stored, None when the network doesn't have the chunk
+    pub content: Option<Bytes>,
+    pub elapsed: Duration,
+}
+
+// Data whose datamap was fetched, so it can be streamed from any offset without fetching it again
+pub struct OpenedData {
+    pub size: usize,
+    read_range: RangeReader,
+}
+
+impl OpenedData {
+    // Held in memory already, ranges are sliced from it
+    pub fn from_bytes(data: Bytes) -> Self {
+        let size = data.len();
+        let read_range = move |start: usize, len: usize| Ok(data.slice(start..start + len));
+        Self {
+            size,
+            read_range: Arc::new(read_range),
+        }
+    }
+
+    // Only the chunks covering a range are fetched, so everything before the offset is skipped.
+    // Several ranges are fetched at once but still come out in order.
+    pub fn stream_from(
+        &self,
+        offset: usize,
+        parallel_chunks: usize,
+    ) -> impl Stream<Item = Result<Bytes, ServerError>> + Send + use<> {
+        let size = self.size;
+        let read_range = self.read_range.clone();
+        let ranges = (offset.min(size)..size)
+            .step_by(RANGE_SIZE)
+            .map(move |start| (start, RANGE_SIZE.min(size - start)));
+        futures::stream::iter(ranges)
+            .map(move |(start, len)| read_blocking(read_range.clone(), start, len))
+            .buffered(parallel_chunks.max(1))
+    }
+}
+
 #[derive(Clone)]
 pub struct Server {
     client: Client,
 }
 
 impl Server {
-    pub async fn new(environment: &str) -> Result<Self, String> {
-        println!("Initializing client with environment: {environment:?}");
+    pub async fn new(environment: &str) -> Result<Self, ServerError> {
+        eprintln!("Initializing client with environment: {environment:?}");
 
         let client = init_client(environment).await?;
-        println!("Client initialized for streaming");
+        eprintln!("Client initialized for streaming");
 
         Ok(Self { client })
     }
@@ -22,33 +156,314 @@ impl Server {
     pub async fn stream_data(
         &self,
         address: &str,
-    ) -> Result<impl Iterator<Item = Result<Bytes, String>> + use<>, String> {
-        println!("Starting to stream data from address: {address}");
+    ) -> Result<impl Stream<Item = Result<Bytes, ServerError>> + Send + use<>, ServerError> {
+        self.stream_data_from(address, 0, DEFAULT_PARALLEL_CHUNKS)
+            .await
+    }
+
+    pub async fn stream_data_from(
+        &self,
+        address: &str,
+        offset: usize,
+        parallel_chunks: usize,
+    ) -> Result<impl Stream<Item = Result<Bytes, ServerError>> + Send + use<>, ServerError> {
+        eprintln!("Starting to stream data from address: {address} at offset {offset}");
+
+        let data = self.open_data(address).await?;
+        Ok(data.stream_from(offset, parallel_chunks))
+    }
+
+    // Fetch one chunk by its address, without decrypting or following anything
+    pub async fn fetch_chunk(&self, address: &str) -> Result<ChunkReport, ServerError> {
+        let chunk_address = ChunkAddress::from_hex(address)
+            .map_err(|e| ServerError::InvalidAddress(format!("Invalid chunk address: {e}")))?;
+        eprintln!("Fetching chunk: {address}");
+
+        let started = Instant::now();
+        let result = self.client.chunk_get(&chunk_address).await;
+        let elapsed = started.elapsed();
+
+        let content = match result {
+            Ok(chunk) => Some(chunk.value().clone()),
+            Err(GetError::RecordNotFound) => None,
+            Err(e) => return Err(ServerError::from_get_error("Failed to get chunk: ", e)),
+        };
+
+        Ok(ChunkReport { content, elapsed })
+    }
+
+    // The chunks behind some data, to probe them one by one: the datamap chunk of a public
+    // address, then the chunks its datamap lists. For big files those hold further datamaps.
+    pub async fn data_map_chunks(&self, address: &str) -> Result<Vec<String>, ServerError> {
+        let mut chunks = Vec::new();
+        let data_map_bytes = match AddressKind::detect(address) {
+            Some(AddressKind::Public) => {
+                let data_address = DataAddress::from_hex(address).map_err(|e| {
+                    ServerError::InvalidAddress(format!("Invalid address format: {e}"))
+                })?;
+                let chunk_address = ChunkAddress::new(*data_address.xorname());
+                chunks.push(chunk_address.to_hex());
+                let chunk = self
+                    .client
+                    .chunk_get(&chunk_address)
+                    .await
+                    .map_err(|e| ServerError::from_get_error("Failed to get datamap: ", e))?;
+                chunk.value().clone()
+            }
+            // The datamap itself is the address
+            Some(AddressKind::PrivateDataMap) => Bytes::from(decode_hex(address)),
+            _ => {
+                return Err(ServerError::InvalidAddress(
+                    "Expected a public data address or a private datamap".to_string(),
+                ));
+            }
+        };
+
+        let data_map: DataMap = rmp_serde::from_slice(&data_map_bytes)
+            .map_err(|e| ServerError::Data(format!("Invalid datamap: {e}")))?;
+        chunks.extend(
+            data_map
+                .infos()
+                .iter()
+                .map(|info| ChunkAddress::new(info.dst_hash).to_hex()),
+        );
+        Ok(chunks)
+    }
+
+    // Follow a pointer, and the pointers it points to, to the data at the end of the chain
+    pub async fn resolve_pointer(
+        &self,
+        address: &str,
+    ) -> Result<Option<ResolvedPointer>, ServerError> {
+        if AddressKind::detect(address) != Some(AddressKind::Pointer) {
+            return Ok(None);
+        }
+        eprintln!("Resolving pointer: {address}");
+
+        let mut pointer_address = PointerAddress::from_hex(address::hex_part(address))
+            .map_err(|e| ServerError::InvalidAddress(format!("Invalid pointer address: {e}")))?;
+        let mut counter = None;
+
+        for _ in 0..MAX_POINTER_HOPS {
+            let pointer = self
+                .client
+                .pointer_get(&pointer_address)
+                .await
+                .map_err(|e| ServerError::Network(format!("Failed to get pointer: {e}")))?;
+            // The counter of the shared pointer, not of the ones it leads to
+            let counter = *counter.get_or_insert(u64::from(pointer.counter()));
+
+            let target = match pointer.target() {
+                PointerTarget::PointerAddress(next) => {
+                    pointer_address = *next;
+                    continue;
+                }
+                PointerTarget::ChunkAddress(chunk_address) => {
+                    DataAddress::new(*chunk_address.xorname()).to_hex()
+                }
+                PointerTarget::ScratchpadAddress(scratchpad_address) => {
+                    format!(
+                        "{}{}",
+                        address::SCRATCHPAD_PREFIX,
+                        scratchpad_address.to_hex()
+                    )
+                }
+                PointerTarget::GraphEntryAddress(graph_entry_address) => {
+                    format!(
+                        "{}{}",
+                        address::GRAPH_ENTRY_PREFIX,
+                        graph_entry_address.to_hex()
+                    )
+                }
+            };
+            eprintln!("Pointer {address} resolved to {target} (counter {counter})");
+            return Ok(Some(ResolvedPointer { target, counter }));
+        }
+
+        Err(ServerError::Data(format!(
+            "Pointer chain is longer than {MAX_POINTER_HOPS} pointers, it may loop"
+        )))
+    }
+
+    // The current version of a scratchpad and its payload. The payload is kept as stored, only
+    // its owner could decrypt it, so this is meant for scratchpads holding public data.
+    pub async fn scratchpad(&self, address: &str) -> Result<(ScratchpadInfo, Bytes), ServerError> {
+        let scratchpad_address = ScratchpadAddress::from_hex(address::hex_part(address))
+            .map_err(|e| ServerError::InvalidAddress(format!("Invalid scratchpad address: {e}")))?;
+        let scratchpad = self
+            .client
+            .scratchpad_get(&scratchpad_address)
+            .await
+            .map_err(|e| ServerError::Network(format!("Failed to get scratchpad: {e}")))?;
 
-        // Parse the address
-        let data_address =
-            DataAddress::from_hex(address).map_err(|e| format!("Invalid address format: {e}"))?;
+        let info = ScratchpadInfo {
+            counter: u64::from(scratchpad.counter()),
+            data_encoding: scratchpad.data_encoding(),
+        };
+        Ok((info, scratchpad.encrypted_data().clone()))
+    }
 
-        // Start streaming
-        let stream = self
+    // One entry of a graph, with the entries following it
+    pub async fn graph_entry(&self, address: &str) -> Result<GraphVersion, ServerError> {
+        let graph_entry_address =
+            GraphEntryAddress::from_hex(address::hex_part(address)).map_err(|e| {
+                ServerError::InvalidAddress(format!("Invalid graph entry address: {e}"))
+            })?;
+        let entry = self
             .client
-            .data_stream_public(&data_address)
+            .graph_entry_get(&graph_entry_address)
             .await
-            .map_err(|e| format!("Failed to start streaming: {e}"))?;
+            .map_err(|e| ServerError::Network(format!("Failed to get graph entry: {e}")))?;
+
+        Ok(GraphVersion {
+            address: graph_entry_address.to_hex(),
+            content: DataAddress::new(XorName(entry.content)).to_hex(),
+            descendants: entry
+                .descendants
+                .iter()
+                .map(|(public_key, _)| GraphEntryAddress::new(*public_key).to_hex())
+                .collect(),
+        })
+    }
+
+    // Fetch the datamap behind an address, to learn the data size and read its ranges. Pointers
+    // are resolved by the caller, once, so every request sees the same target.
+    pub async fn open_data(&self, address: &str) -> Result<OpenedData, ServerError> {
+        // Parse the address and start streaming, public data or private data from its datamap
+        let stream = match AddressKind::detect(address) {
+            Some(AddressKind::Public) => {
+                let data_address = DataAddress::from_hex(address).map_err(|e| {
+                    ServerError::InvalidAddress(format!("Invalid address format: {e}"))
+                })?;
+                self.client.data_stream_public(&data_address).await
+            }
+            Some(AddressKind::PrivateDataMap) => {
+                let data_map = DataMapChunk::from_hex(address).map_err(|e| {
+                    ServerError::InvalidAddress(format!("Invalid datamap format: {e}"))
+                })?;
+                self.client.data_stream(&data_map).await
+            }
+            // Small enough to fetch whole, ranges are read from memory
+            Some(AddressKind::Scratchpad) => {
+                let (_, data) = self.scratchpad(address).await?;
+                return Ok(OpenedData::from_bytes(data));
+            }
+            Some(AddressKind::GraphEntry) => {
+                return Err(ServerError::InvalidAddress(
+                    "A graph entry holds no file itself, pick one of its versions".to_string(),
+                ));
+            }
+            Some(AddressKind::Pointer) => {
+                return Err(ServerError::InvalidAddress(
+                    "A pointer must be resolved before reading its data".to_string(),
+                ));
+            }
+            None => {
+                return Err(ServerError::InvalidAddress(
+                    "Invalid address format: expected a hex data address or datamap".to_string(),
+                ));
+            }
+        }
+        .map_err(|e| ServerError::from_get_error("Failed to start streaming: ", e))?;
+
+        let size = stream.data_size();
+        let read_range = move |start, len| {
+            stream
+                .get_range(start, len)
+                .map_err(|e| ServerError::from_get_error("", e))
+        };
 
-        // Convert GetError to String for easier error handling
-        Ok(stream.map(|chunk_result| chunk_result.map_err(|e| e.to_string())))
+        Ok(OpenedData {
+            size,
+            read_range: Arc::new(read_range),
+        })
     }
+
+    // The entries of a public archive, or None when the address is a plain file. Pointers are
+    // resolved by the caller.
+    pub async fn archive_entries(
+        &self,
+        address: &str,
+    ) -> Result<Option<Vec<ArchiveEntry>>, ServerError> {
+        if AddressKind::detect(address) != Some(AddressKind::Public) {
+            return Ok(None);
+        }
+        eprintln!("Checking if address is an archive: {address}");
+
+        let OpenedData { size, read_range } = self.open_data(address).await?;
+        if size == 0 || size > MAX_ARCHIVE_SIZE {
+            return Ok(None);
+        }
+        // Only the first chunk is fetched to rule out most files
+        let head = read_blocking(read_range.clone(), 0, 1).await?;
+        if !may_be_archive(&head) {
+            return Ok(None);
+        }
+        let bytes = read_blocking(read_range, 0, size).await?;
+
+        // Anything that doesn't deserialize as an archive is a regular file
+        let archive = match PublicArchive::from_bytes(bytes) {
+            Ok(archive) => archive,
+            Err(_) => return Ok(None),
+        };
+
+        let entries = archive
+            .files()
+            .into_iter()
+            .map(|(path, data_address, metadata)| ArchiveEntry {
+                path,
+                address: data_address.to_hex(),
+                size: metadata.size,
+                modified: metadata.modified,
+            })
+            .collect();
+
+        Ok(Some(entries))
+    }
+}
+
+// Only called on addresses `AddressKind::detect` accepted, so even length and hex digits only
+fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or_default())
+        .collect()
+}
+
+// Archives are serialized with msgpack, as a map or an array
+fn may_be_archive(head: &[u8]) -> bool {
+    matches!(head.first(), Some(0x80..=0x9f | 0xdc..=0xdf))
+}
+
+// Fetching and decrypting a range blocks, keep it off the async workers
+async fn read_blocking(
+    read_range: RangeReader,
+    start: usize,
+    len: usize,
+) -> Result<Bytes, ServerError> {
+    tokio::task::spawn_blocking(move || read_range(start, len))
+        .await
+        .unwrap_or_else(|e| Err(ServerError::Network(format!("Read task failed: {e}"))))
 }
 
-async fn init_client(environment: &str) -> Result<Client, String> {
+async fn init_client(environment: &str) -> Result<Client, ServerError> {
     let res = match environment {
         "local" => Client::init_local().await,
         "alpha" => Client::init_alpha().await,
-        _ => Client::init().await, // "autonomi"
+        "autonomi" => Client::init().await,
+        // A network defined in the config file
+        name => {
+            let config = config::load();
+            let client_config = config
+                .environment(name)
+                .ok_or_else(|| ServerError::Config(format!("Unknown network: {name}")))?
+                .client_config()
+                .map_err(ServerError::Config)?;
+            Client::init_with_config(client_config).await
+        }
     };
     res.map_err(|e| {
-        println!("Error initializing client: {e}");
-        format!("Error initializing client: {e}")
+        eprintln!("Error initializing client: {e}");
+        ServerError::Connection(format!("Error initializing client: {e}"))
     })
 }
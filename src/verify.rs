@@ -0,0 +1,53 @@
+use crate::address::AddressKind;
+use crate::format_file_size;
+
+use autonomi::data::private::DataMapChunk;
+use autonomi::data::DataAddress;
+use autonomi::Bytes;
+use std::path::Path;
+
+// Self-encryption needs the whole file and its encrypted chunks in memory at once
+pub const MAX_VERIFY_SIZE: u64 = 1 << 30;
+
+// Self-encrypt the local file like the uploader did, and check it gives back the same address.
+// Self-encryption is deterministic, so any changed byte gives a different datamap.
+pub fn verify_file(path: &Path, address: &str) -> Result<bool, String> {
+    let kind = AddressKind::detect(address)
+        .ok_or_else(|| "Invalid address format: cannot verify".to_string())?;
+    // Mutable data has no fixed content to compare with
+    let public = match kind {
+        AddressKind::Public => true,
+        AddressKind::PrivateDataMap => false,
+        AddressKind::Pointer => {
+            return Err("Cannot verify against a pointer, use the address it points to".to_string())
+        }
+        AddressKind::Scratchpad => {
+            return Err("Scratchpads change over time, there is nothing to verify".to_string())
+        }
+        AddressKind::GraphEntry => {
+            return Err("Cannot verify against a graph entry, use one of its versions".to_string())
+        }
+    };
+
+    let size = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to read file: {e}"))?
+        .len();
+    if size > MAX_VERIFY_SIZE {
+        return Err(format!(
+            "Files over {} are too large to verify",
+            format_file_size(MAX_VERIFY_SIZE as usize)
+        ));
+    }
+
+    let content = std::fs::read(path).map_err(|e| format!("Failed to read file: {e}"))?;
+    let (data_map_chunk, _chunks) = autonomi::self_encryption::encrypt(Bytes::from(content))
+        .map_err(|e| format!("Failed to self-encrypt file: {e}"))?;
+
+    let local_address = if public {
+        DataAddress::new(*data_map_chunk.name()).to_hex()
+    } else {
+        DataMapChunk::from(data_map_chunk).to_hex()
+    };
+
+    Ok(local_address.eq_ignore_ascii_case(address))
+}